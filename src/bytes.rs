@@ -0,0 +1,200 @@
+use crate::{geometry::connection_code, Castle, CastleError, Connection, PlacedRoom, Result, Room};
+use std::{collections::BTreeMap, convert::TryFrom};
+
+// Bumped whenever the layout written by to_bytes changes.
+const BYTES_VERSION: u8 = 1;
+
+impl Castle {
+    /*
+     * Version byte, damage, then a u16 room count and for each room: x, y,
+     * rotation as u16, flags (1 throne, 2 reflected), treasure, the four
+     * connection codes and the name as a u16 length and UTF-8. Multi-byte
+     * numbers are little endian. The link table is not written. Fails with
+     * InvalidFormat when there are more than 65535 rooms or a name is longer
+     * than 65535 bytes.
+     */
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut data = vec![BYTES_VERSION, self.damage];
+        data.extend(length(self.rooms.len())?.to_le_bytes());
+        for ((x, y), room) in self.rooms.iter() {
+            data.extend([*x as u8, *y as u8]);
+            data.extend(room.rotation.to_le_bytes());
+            data.push(room.info.throne as u8 | (room.reflected as u8) << 1);
+            data.push(room.info.treasure);
+            data.extend(room.info.connections.iter().map(connection_code));
+            data.extend(length(room.info.name.len())?.to_le_bytes());
+            data.extend(room.info.name.as_bytes());
+        }
+        Ok(data)
+    }
+    pub fn from_bytes(data: &[u8]) -> Result<Castle> {
+        let mut reader = Reader { data };
+        let version = reader.u8()?;
+        if version != BYTES_VERSION {
+            return Err(CastleError::UnsupportedVersion(version));
+        }
+        let damage = reader.u8()?;
+        let mut rooms = BTreeMap::new();
+        for _ in 0..reader.u16()? {
+            let pos = (reader.u8()? as i8, reader.u8()? as i8);
+            let rotation = reader.u16()?;
+            let flags = reader.u8()?;
+            if flags > 3 {
+                return Err(CastleError::InvalidFormat);
+            }
+            let treasure = reader.u8()?;
+            let mut connections = [Connection::None; 4];
+            for connection in connections.iter_mut() {
+                *connection = connection_from_code(reader.u8()?)?;
+            }
+            let len = reader.u16()? as usize;
+            let name = String::from_utf8(reader.take(len)?.to_vec())
+                .map_err(|_| CastleError::InvalidFormat)?;
+            let room = PlacedRoom {
+                info: Room {
+                    name,
+                    throne: flags & 1 != 0,
+                    treasure,
+                    connections,
+                },
+                rotation,
+                reflected: flags & 2 != 0,
+            };
+            if rooms.insert(pos, room).is_some() {
+                return Err(CastleError::TakenPosition);
+            }
+        }
+        if !reader.data.is_empty() {
+            return Err(CastleError::InvalidFormat);
+        }
+        Ok(Castle {
+            rooms,
+            damage,
+            link_table: None,
+        })
+    }
+}
+
+fn length(len: usize) -> Result<u16> {
+    u16::try_from(len).map_err(|_| CastleError::InvalidFormat)
+}
+
+fn connection_from_code(code: u8) -> Result<Connection> {
+    Ok(match code {
+        0 => Connection::None,
+        1 => Connection::Wild,
+        2 | 3 => Connection::Diamond(code == 3),
+        4 | 5 => Connection::Cross(code == 5),
+        6 | 7 => Connection::Moon(code == 7),
+        _ => return Err(CastleError::InvalidFormat),
+    })
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(CastleError::InvalidFormat);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{room, throne};
+    use crate::{Action, Castle, CastleError, Connection, PlacedRoom};
+
+    fn castle() -> Castle {
+        let vault = room(
+            "Small Vault",
+            2,
+            [
+                Connection::None,
+                Connection::Moon(false),
+                Connection::None,
+                Connection::Cross(true),
+            ],
+        );
+        let mut castle = Castle::new(throne())
+            .apply(Action::Place(vault.clone(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(vault.clone(), (0, -1), 270))
+            .unwrap();
+        castle
+            .rooms
+            .insert((-1, 0), PlacedRoom::from(vault, 180).reflect());
+        castle.damage = 2;
+        castle
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let castle = castle();
+        let data = castle.to_bytes().unwrap();
+        assert_eq!(data[0], 1);
+        assert_eq!(Castle::from_bytes(&data).unwrap(), castle);
+        let empty = Castle::new_empty();
+        assert_eq!(
+            Castle::from_bytes(&empty.to_bytes().unwrap()).unwrap(),
+            empty
+        );
+    }
+
+    #[test]
+    fn test_bytes_oversized_name() {
+        let long = room(&"a".repeat(65536), 0, [Connection::Wild; 4]);
+        assert!(matches!(
+            Castle::new(long).to_bytes(),
+            Err(CastleError::InvalidFormat)
+        ));
+        let longest = room(&"a".repeat(65535), 0, [Connection::Wild; 4]);
+        let castle = Castle::new(longest);
+        assert_eq!(
+            Castle::from_bytes(&castle.to_bytes().unwrap()).unwrap(),
+            castle
+        );
+    }
+
+    #[test]
+    fn test_bytes_corrupted() {
+        let data = castle().to_bytes().unwrap();
+        for len in 0..data.len() {
+            assert!(matches!(
+                Castle::from_bytes(&data[..len]),
+                Err(CastleError::InvalidFormat)
+            ));
+        }
+        let mut extra = data.clone();
+        extra.push(0);
+        assert!(matches!(
+            Castle::from_bytes(&extra),
+            Err(CastleError::InvalidFormat)
+        ));
+        let mut future = data.clone();
+        future[0] = 2;
+        assert!(matches!(
+            Castle::from_bytes(&future),
+            Err(CastleError::UnsupportedVersion(2))
+        ));
+        // The first connection code of the first room.
+        let mut bad_connection = data;
+        bad_connection[10] = 9;
+        assert!(matches!(
+            Castle::from_bytes(&bad_connection),
+            Err(CastleError::InvalidFormat)
+        ));
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{Action, CastleError, Pos, Result, Room, Rot};
+use serde::{Deserialize, Serialize};
+
+/*
+ * An Action with the placed room replaced by a caller chosen id, so logs don't
+ * have to carry whole rooms around.
+ */
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum CompactAction {
+    Place(u32, Pos, Rot),
+    Move(Pos, Pos, Rot),
+    Swap(Pos, Pos),
+    Discard(Vec<Pos>),
+    Damage(u8, u8, u8),
+}
+
+impl Action {
+    pub fn to_compact(&self, room_id: impl Fn(&Room) -> Option<u32>) -> Result<CompactAction> {
+        Ok(match self {
+            Action::Place(room, pos, rot) => {
+                CompactAction::Place(room_id(room).ok_or(CastleError::InvalidFormat)?, *pos, *rot)
+            }
+            Action::Move(from, to, rot) => CompactAction::Move(*from, *to, *rot),
+            Action::Swap(pos_1, pos_2) => CompactAction::Swap(*pos_1, *pos_2),
+            Action::Discard(poses) => CompactAction::Discard(poses.clone()),
+            Action::Damage(diamond, cross, moon) => CompactAction::Damage(*diamond, *cross, *moon),
+        })
+    }
+    /*
+     * Tag followed by comma separated numbers, e.g. "P3,0,-1,90" or "D1,0,2,0"
+     */
+    pub fn to_compact_string(&self, room_id: impl Fn(&Room) -> Option<u32>) -> Result<String> {
+        let (tag, numbers): (char, Vec<String>) = match self.to_compact(room_id)? {
+            CompactAction::Place(id, (x, y), rot) => (
+                'P',
+                vec![
+                    id.to_string(),
+                    x.to_string(),
+                    y.to_string(),
+                    rot.to_string(),
+                ],
+            ),
+            CompactAction::Move((x1, y1), (x2, y2), rot) => (
+                'M',
+                vec![
+                    x1.to_string(),
+                    y1.to_string(),
+                    x2.to_string(),
+                    y2.to_string(),
+                    rot.to_string(),
+                ],
+            ),
+            CompactAction::Swap((x1, y1), (x2, y2)) => (
+                'S',
+                vec![
+                    x1.to_string(),
+                    y1.to_string(),
+                    x2.to_string(),
+                    y2.to_string(),
+                ],
+            ),
+            CompactAction::Discard(poses) => (
+                'D',
+                poses
+                    .iter()
+                    .flat_map(|(x, y)| vec![x.to_string(), y.to_string()])
+                    .collect(),
+            ),
+            CompactAction::Damage(diamond, cross, moon) => (
+                'H',
+                vec![diamond.to_string(), cross.to_string(), moon.to_string()],
+            ),
+        };
+        Ok(format!("{}{}", tag, numbers.join(",")))
+    }
+    pub fn from_compact_string(s: &str, room: impl Fn(u32) -> Option<Room>) -> Result<Action> {
+        let mut chars = s.chars();
+        let tag = chars.next().ok_or(CastleError::InvalidFormat)?;
+        let rest = chars.as_str();
+        let numbers: Vec<i64> = if rest.is_empty() {
+            Vec::new()
+        } else {
+            rest.split(',')
+                .map(|n| n.parse().map_err(|_| CastleError::InvalidFormat))
+                .collect::<Result<_>>()?
+        };
+        let action = match (tag, numbers.as_slice()) {
+            ('P', [id, x, y, rot]) => {
+                CompactAction::Place(number(*id)?, (number(*x)?, number(*y)?), number(*rot)?)
+            }
+            ('M', [x1, y1, x2, y2, rot]) => CompactAction::Move(
+                (number(*x1)?, number(*y1)?),
+                (number(*x2)?, number(*y2)?),
+                number(*rot)?,
+            ),
+            ('S', [x1, y1, x2, y2]) => {
+                CompactAction::Swap((number(*x1)?, number(*y1)?), (number(*x2)?, number(*y2)?))
+            }
+            ('D', poses) if poses.len() % 2 == 0 => CompactAction::Discard(
+                poses
+                    .chunks(2)
+                    .map(|p| Ok((number(p[0])?, number(p[1])?)))
+                    .collect::<Result<_>>()?,
+            ),
+            ('H', [diamond, cross, moon]) => {
+                CompactAction::Damage(number(*diamond)?, number(*cross)?, number(*moon)?)
+            }
+            _ => return Err(CastleError::InvalidFormat),
+        };
+        action.to_action(room)
+    }
+}
+
+impl CompactAction {
+    pub fn to_action(self, room: impl Fn(u32) -> Option<Room>) -> Result<Action> {
+        Ok(match self {
+            CompactAction::Place(id, pos, rot) => {
+                Action::Place(room(id).ok_or(CastleError::InvalidFormat)?, pos, rot)
+            }
+            CompactAction::Move(from, to, rot) => Action::Move(from, to, rot),
+            CompactAction::Swap(pos_1, pos_2) => Action::Swap(pos_1, pos_2),
+            CompactAction::Discard(poses) => Action::Discard(poses),
+            CompactAction::Damage(diamond, cross, moon) => Action::Damage(diamond, cross, moon),
+        })
+    }
+}
+
+fn number<T: std::convert::TryFrom<i64>>(n: i64) -> Result<T> {
+    T::try_from(n).map_err(|_| CastleError::InvalidFormat)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::throne;
+    use crate::{Action, Room};
+
+    #[test]
+    fn test_compact_round_trip() {
+        let rooms = [throne()];
+        let room_id = |room: &Room| rooms.iter().position(|r| r == room).map(|i| i as u32);
+        let room = |id: u32| rooms.get(id as usize).cloned();
+        let actions = vec![
+            Action::Place(throne(), (0, -1), 90),
+            Action::Move((1, 0), (-1, 0), 270),
+            Action::Swap((1, 0), (0, 1)),
+            Action::Discard(vec![(1, 0), (-2, 3)]),
+            Action::Discard(vec![]),
+            Action::Damage(1, 0, 2),
+        ];
+        for action in actions {
+            let compact = action.to_compact_string(room_id).unwrap();
+            assert_eq!(Action::from_compact_string(&compact, room).unwrap(), action);
+        }
+        assert_eq!(
+            Action::Place(throne(), (0, -1), 90)
+                .to_compact_string(room_id)
+                .unwrap(),
+            "P0,0,-1,90"
+        );
+        assert!(Action::from_compact_string("P7,0,0,0", room).is_err());
+        assert!(Action::from_compact_string("S1,2", room).is_err());
+    }
+}
@@ -0,0 +1,311 @@
+use crate::{Action, Castle, DiscardPolicy, Result, Room};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/*
+ * net_damage is the damage the attack added after links absorbed it.
+ * rooms_destroyed is only non zero when the damage wiped out every room.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct DamageOutcome {
+    pub net_damage: u8,
+    pub rooms_destroyed: usize,
+    pub castle_lost: bool,
+}
+
+/*
+ * How an attack's symbols meet the castle's links, as (diamond, cross, moon).
+ * Wild links only soak up damage when the total owed is more than them.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct DamageBreakdown {
+    pub absorbed: (u8, u8, u8),
+    pub unabsorbed: (u8, u8, u8),
+    pub wild_applied: u8,
+    pub net_damage: u8,
+}
+
+impl Castle {
+    pub fn damage_breakdown(
+        &self,
+        diamond_damage: u8,
+        cross_damage: u8,
+        moon_damage: u8,
+    ) -> Result<DamageBreakdown> {
+        let (diamond_link, cross_link, moon_link, wild_link) = self.get_links()?;
+        let unabsorbed = (
+            diamond_damage.saturating_sub(diamond_link),
+            cross_damage.saturating_sub(cross_link),
+            moon_damage.saturating_sub(moon_link),
+        );
+        let absorbed = (
+            diamond_damage - unabsorbed.0,
+            cross_damage - unabsorbed.1,
+            moon_damage - unabsorbed.2,
+        );
+        // Saturates: past u8::MAX every room is lost anyway.
+        let owed = self
+            .damage
+            .saturating_add(unabsorbed.0)
+            .saturating_add(unabsorbed.1)
+            .saturating_add(unabsorbed.2);
+        let wild_applied = if owed > wild_link { wild_link } else { 0 };
+        Ok(DamageBreakdown {
+            absorbed,
+            unabsorbed,
+            wild_applied,
+            net_damage: (owed - wild_applied).saturating_sub(self.damage),
+        })
+    }
+    pub fn action_damage_detailed(
+        &self,
+        diamond_damage: u8,
+        cross_damage: u8,
+        moon_damage: u8,
+    ) -> Result<(Castle, DamageOutcome)> {
+        let breakdown = self.damage_breakdown(diamond_damage, cross_damage, moon_damage)?;
+        let (diamond, cross, moon) = breakdown.unabsorbed;
+        let mut castle = self.clone();
+        castle.damage = castle
+            .damage
+            .saturating_add(diamond)
+            .saturating_add(cross)
+            .saturating_add(moon)
+            - breakdown.wild_applied;
+        let net_damage = castle.damage.saturating_sub(self.damage);
+        let mut rooms_destroyed = 0;
+        if castle.damage as usize >= castle.rooms.len() {
+            rooms_destroyed = castle.rooms.len();
+            castle.damage -= castle.rooms.len() as u8;
+            castle.rooms = BTreeMap::new();
+        }
+        let castle_lost = castle.is_lost();
+        Ok((
+            castle,
+            DamageOutcome {
+                net_damage,
+                rooms_destroyed,
+                castle_lost,
+            },
+        ))
+    }
+    /*
+     * Applies the attack, then discards rooms chosen by policy until the
+     * damage is paid off or the castle is lost.
+     */
+    pub fn take_attack(
+        &self,
+        diamond: u8,
+        cross: u8,
+        moon: u8,
+        policy: &dyn DiscardPolicy,
+    ) -> Result<Castle> {
+        let castle = self.action_damage(diamond, cross, moon)?;
+        if castle.damage > 0 && !castle.is_lost() {
+            castle.auto_discard_with(policy)
+        } else {
+            Ok(castle)
+        }
+    }
+    /*
+     * A whole turn: the build action, checked against the shop, then the
+     * attack as (diamond, cross, moon) resolved with take_attack.
+     */
+    pub fn play_turn(
+        &self,
+        shop: &[Room],
+        build: Action,
+        attack: (u8, u8, u8),
+        discard_policy: &dyn DiscardPolicy,
+    ) -> Result<Castle> {
+        let (diamond, cross, moon) = attack;
+        self.apply_from_shop(&build, shop)?
+            .take_attack(diamond, cross, moon, discard_policy)
+    }
+    /*
+     * Treasure left after the attack, once any damage it causes has been paid
+     * off by discarding the least valuable rooms. Fails if those discards do.
+     */
+    pub fn treasure_after_attack(&self, diamond: u8, cross: u8, moon: u8) -> Result<u32> {
+        let castle = self.action_damage(diamond, cross, moon)?;
+        if castle.damage > 0 && !castle.is_lost() {
+            castle.auto_discard()?.get_treasure()
+        } else {
+            castle.get_treasure()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{room, small_vault, throne};
+    use crate::{
+        Action, Castle, CastleError, Connection, DamageBreakdown, LeastValuable, PlacedRoom,
+        PreserveTreasure,
+    };
+
+    fn castle() -> Castle {
+        let vault = small_vault();
+        Castle::new(throne())
+            .apply(Action::Place(vault.clone(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(vault, (0, 1), 90))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_damage_detailed_absorbed() {
+        let (damaged, outcome) = castle().action_damage_detailed(0, 3, 1).unwrap();
+        assert_eq!(damaged, castle().action_damage(0, 3, 1).unwrap());
+        assert_eq!(outcome.net_damage, 2);
+        assert_eq!(outcome.rooms_destroyed, 0);
+        assert!(!outcome.castle_lost);
+    }
+
+    #[test]
+    fn test_damage_detailed_destroyed() {
+        let (damaged, outcome) = castle().action_damage_detailed(5, 0, 5).unwrap();
+        assert!(damaged.rooms.is_empty());
+        assert_eq!(outcome.net_damage, 10);
+        assert_eq!(outcome.rooms_destroyed, 3);
+        assert!(outcome.castle_lost);
+    }
+
+    #[test]
+    fn test_treasure_after_attack() {
+        let vault = room(
+            "Vault",
+            3,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Cross(true),
+            ],
+        );
+        let closet = room(
+            "Closet",
+            0,
+            [
+                Connection::None,
+                Connection::Cross(false),
+                Connection::None,
+                Connection::None,
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault, (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(closet, (-1, 0), 0))
+            .unwrap();
+        assert_eq!(castle.get_treasure().unwrap(), 3);
+        assert_eq!(castle.treasure_after_attack(0, 0, 1).unwrap(), 3);
+        assert_eq!(castle.treasure_after_attack(0, 0, 2).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_damage_breakdown() {
+        let hall = room(
+            "Hall",
+            0,
+            [
+                Connection::None,
+                Connection::Diamond(false),
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        // Three cross links into the throne and a wild link between the two thrones.
+        let castle = castle()
+            .apply(Action::Place(hall, (-1, 0), 180))
+            .unwrap()
+            .apply(Action::Place(throne(), (0, -1), 0))
+            .unwrap();
+        assert_eq!(castle.get_links().unwrap(), (0, 3, 0, 1));
+        let breakdown = castle.damage_breakdown(2, 1, 1).unwrap();
+        assert_eq!(
+            breakdown,
+            DamageBreakdown {
+                absorbed: (0, 1, 0),
+                unabsorbed: (2, 0, 1),
+                wild_applied: 1,
+                net_damage: 2,
+            }
+        );
+        assert_eq!(castle.action_damage(2, 1, 1).unwrap().damage, 2);
+        let breakdown = castle.damage_breakdown(1, 0, 0).unwrap();
+        assert_eq!(breakdown.wild_applied, 0);
+        assert_eq!(breakdown.net_damage, 1);
+    }
+
+    #[test]
+    fn test_take_attack() {
+        let castle = castle();
+        let resolved = castle.take_attack(0, 3, 0, &LeastValuable).unwrap();
+        assert_eq!(resolved.damage, 0);
+        assert_eq!(resolved.rooms.len(), castle.rooms.len() - 1);
+        let untouched = castle.take_attack(0, 2, 0, &PreserveTreasure).unwrap();
+        assert_eq!(untouched, castle);
+        assert!(castle
+            .take_attack(5, 5, 5, &LeastValuable)
+            .unwrap()
+            .is_lost());
+    }
+
+    #[test]
+    fn test_damage_saturates() {
+        let breakdown = Castle::new(throne()).damage_breakdown(200, 200, 0).unwrap();
+        assert_eq!(breakdown.unabsorbed, (200, 200, 0));
+        assert_eq!(breakdown.net_damage, u8::MAX);
+        let castle = Castle::new(throne())
+            .apply(Action::Damage(200, 200, 0))
+            .unwrap();
+        assert!(castle.rooms.is_empty());
+        assert!(castle.is_lost());
+    }
+
+    #[test]
+    fn test_damage_malformed() {
+        let vault = small_vault();
+        // The throne's wild side faces a wall of the vault.
+        let mut broken = Castle::new(throne());
+        broken.rooms.insert((0, 1), PlacedRoom::from(vault, 0));
+        assert!(matches!(
+            broken.action_damage(0, 1, 0),
+            Err(CastleError::InvalidConnection)
+        ));
+        assert!(broken.take_attack(0, 1, 0, &LeastValuable).is_err());
+        assert!(broken.treasure_after_attack(0, 1, 0).is_err());
+        assert!(broken
+            .play_turn(&[], Action::Damage(0, 0, 0), (0, 1, 0), &LeastValuable)
+            .is_err());
+    }
+
+    #[test]
+    fn test_play_turn() {
+        let vault = small_vault();
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault.clone(), (1, 0), 0))
+            .unwrap();
+        assert_eq!(castle.action_damage(0, 2, 0).unwrap().damage, 1);
+        let shop = [vault.clone()];
+        let played = castle
+            .play_turn(
+                &shop,
+                Action::Place(vault.clone(), (-1, 0), 180),
+                (0, 2, 0),
+                &LeastValuable,
+            )
+            .unwrap();
+        assert_eq!(played.damage, 0);
+        assert_eq!(played.rooms.len(), 3);
+        assert!(castle
+            .play_turn(
+                &[],
+                Action::Place(vault, (-1, 0), 180),
+                (0, 2, 0),
+                &LeastValuable
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,280 @@
+use crate::{Castle, CastleError, Pos, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct DiscardImpact {
+    pub legal: bool,
+    pub links_lost: (u8, u8, u8, u8),
+    pub treasure_lost: u32,
+    pub disconnects_castle: bool,
+}
+
+/*
+ * Picks the next room to discard from a damaged castle, or None if it can't.
+ * Errors from weighing the rooms, like a link the castle can't make, are
+ * passed on.
+ */
+pub trait DiscardPolicy {
+    fn choose(&self, castle: &Castle) -> Result<Option<Pos>>;
+}
+
+/*
+ * Discards whatever Castle::least_valuable_discardable picks
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct LeastValuable;
+
+/*
+ * The least valuable room among those not scoring treasure, falling back to
+ * LeastValuable once every discardable room scores.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct PreserveTreasure;
+
+impl DiscardPolicy for LeastValuable {
+    fn choose(&self, castle: &Castle) -> Result<Option<Pos>> {
+        castle.least_valuable_discardable()
+    }
+}
+
+impl DiscardPolicy for PreserveTreasure {
+    fn choose(&self, castle: &Castle) -> Result<Option<Pos>> {
+        let mut barren = Vec::new();
+        for pos in castle.possible_discard() {
+            if castle.room_treasure(pos)? == 0 {
+                barren.push(pos);
+            }
+        }
+        match castle.least_valuable_of(barren)? {
+            Some(pos) => Ok(Some(pos)),
+            None => castle.least_valuable_discardable(),
+        }
+    }
+}
+
+impl Castle {
+    /*
+     * What discarding the room at pos would cost, without discarding it.
+     * legal is only true while there is damage to pay off.
+     */
+    pub fn discard_impact(&self, pos: Pos) -> Result<DiscardImpact> {
+        if !self.rooms.contains_key(&pos) {
+            return Err(CastleError::EmptyPosition);
+        }
+        let legal = self.damage > 0 && self.possible_discard().contains(&pos);
+
+        let mut castle = self.clone();
+        castle.rooms.remove(&pos);
+
+        let (diamond, cross, moon, wild) = self.get_links()?;
+        let (diamond_after, cross_after, moon_after, wild_after) = castle.get_links()?;
+        let links_lost = (
+            diamond - diamond_after,
+            cross - cross_after,
+            moon - moon_after,
+            wild - wild_after,
+        );
+        let treasure_lost = self.get_treasure()?.saturating_sub(castle.get_treasure()?);
+        let disconnects_castle = match castle.rooms.keys().next() {
+            Some(start) => castle.reachable_from(*start).len() < castle.rooms.len(),
+            None => false,
+        };
+        Ok(DiscardImpact {
+            legal,
+            links_lost,
+            treasure_lost,
+            disconnects_castle,
+        })
+    }
+    /*
+     * The discardable room costing the least treasure, then the fewest links.
+     * Ties go to the smallest position so the choice is deterministic.
+     */
+    pub fn least_valuable_discardable(&self) -> Result<Option<Pos>> {
+        self.least_valuable_of(self.possible_discard())
+    }
+    pub fn auto_discard(&self) -> Result<Castle> {
+        self.auto_discard_with(&LeastValuable)
+    }
+    /*
+     * Like auto_discard, but rooms scoring treasure are only discarded once
+     * every discardable room left is one, even if that costs more links or
+     * leaves other rooms unpowered.
+     */
+    pub fn auto_discard_preserving_treasure(&self) -> Result<Castle> {
+        self.auto_discard_with(&PreserveTreasure)
+    }
+    /*
+     * Discards the rooms chosen by policy until the damage is paid off or the
+     * castle is lost.
+     */
+    pub fn auto_discard_with(&self, policy: &dyn DiscardPolicy) -> Result<Castle> {
+        if self.damage == 0 {
+            return Err(CastleError::NoDamage);
+        }
+        let mut castle = self.clone();
+        while castle.damage > 0 && !castle.is_lost() {
+            match policy.choose(&castle)? {
+                Some(pos) => castle = castle.action_discard_one(pos)?,
+                None => return Err(CastleError::MustDiscard),
+            }
+        }
+        Ok(castle)
+    }
+    fn least_valuable_of(&self, candidates: Vec<Pos>) -> Result<Option<Pos>> {
+        let mut costs = Vec::new();
+        for pos in candidates {
+            let impact = self.discard_impact(pos)?;
+            let (diamond, cross, moon, wild) = impact.links_lost;
+            costs.push((
+                impact.treasure_lost,
+                diamond as u16 + cross as u16 + moon as u16 + wild as u16,
+                pos,
+            ));
+        }
+        Ok(costs.into_iter().min().map(|(_, _, pos)| pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{empty_link_table, room, small_vault, throne};
+    use crate::{Action, Castle, CastleError, Connection};
+
+    fn line_castle() -> Castle {
+        let bridge = room(
+            "Corridor",
+            0,
+            [
+                Connection::None,
+                Connection::Cross(true),
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let vault = small_vault();
+        Castle::new(throne())
+            .apply(Action::Place(bridge, (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(vault, (2, 0), 0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_discard_impact_dead_end() {
+        let mut castle = line_castle();
+        castle.damage = 1;
+        let impact = castle.discard_impact((2, 0)).unwrap();
+        assert!(impact.legal);
+        assert_eq!(impact.links_lost, (0, 1, 0, 0));
+        assert_eq!(impact.treasure_lost, 1);
+        assert!(!impact.disconnects_castle);
+    }
+
+    #[test]
+    fn test_discard_impact_bridge() {
+        let mut castle = line_castle();
+        castle.damage = 1;
+        let impact = castle.discard_impact((1, 0)).unwrap();
+        assert!(!impact.legal);
+        assert_eq!(impact.links_lost, (0, 2, 0, 0));
+        // The vault loses its power along with the corridor.
+        assert_eq!(impact.treasure_lost, 1);
+        assert!(impact.disconnects_castle);
+    }
+
+    #[test]
+    fn test_least_valuable_discardable() {
+        let mut castle = line_castle();
+        castle.damage = 1;
+        assert_eq!(castle.least_valuable_discardable().unwrap(), Some((2, 0)));
+    }
+
+    #[test]
+    fn test_auto_discard_bad_link_table() {
+        let mut castle = line_castle().with_link_table(empty_link_table());
+        castle.damage = 1;
+        assert!(matches!(
+            castle.auto_discard(),
+            Err(CastleError::InvalidConnection)
+        ));
+        assert!(matches!(
+            castle.auto_discard_preserving_treasure(),
+            Err(CastleError::InvalidConnection)
+        ));
+    }
+
+    #[test]
+    fn test_auto_discard() {
+        let mut castle = line_castle();
+        castle.damage = 2;
+        let resolved = castle.auto_discard().unwrap();
+        assert_eq!(resolved.damage, 0);
+        assert!(resolved.rooms.len() < castle.rooms.len());
+    }
+
+    #[test]
+    fn test_auto_discard_no_damage() {
+        assert!(matches!(
+            line_castle().auto_discard(),
+            Err(CastleError::NoDamage)
+        ));
+    }
+
+    #[test]
+    fn test_discard_impact_empty() {
+        assert!(line_castle().discard_impact((5, 5)).is_err());
+    }
+
+    #[test]
+    fn test_auto_discard_preserving_treasure() {
+        let barren = room(
+            "Corridor",
+            0,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::Cross(false),
+                Connection::Cross(false),
+            ],
+        );
+        let small_vault = room(
+            "Small Vault",
+            1,
+            [
+                Connection::Cross(true),
+                Connection::Moon(false),
+                Connection::None,
+                Connection::None,
+            ],
+        );
+        let vault = room(
+            "Vault",
+            3,
+            [
+                Connection::Cross(true),
+                Connection::None,
+                Connection::None,
+                Connection::Moon(false),
+            ],
+        );
+        let mut castle = Castle::new(throne())
+            .apply(Action::Place(barren, (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(small_vault, (0, 1), 0))
+            .unwrap()
+            .apply(Action::Place(vault, (1, 1), 0))
+            .unwrap();
+        castle.damage = 1;
+        // Losing the corridor unpowers the big vault, so the cheapest discard is the small vault.
+        let naive = castle.auto_discard().unwrap();
+        assert!(!naive.rooms.contains_key(&(0, 1)));
+        let preserving = castle.auto_discard_preserving_treasure().unwrap();
+        assert!(!preserving.rooms.contains_key(&(1, 0)));
+        assert!(preserving.rooms.contains_key(&(0, 1)));
+        assert!(matches!(
+            line_castle().auto_discard_preserving_treasure(),
+            Err(CastleError::NoDamage)
+        ));
+    }
+}
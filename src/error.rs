@@ -1,3 +1,4 @@
+use crate::{Pos, RoomError};
 use std::{error::Error, fmt};
 
 #[derive(Debug)]
@@ -10,6 +11,16 @@ pub enum CastleError {
     NotNearlyOuterRoom,
     MustDiscard,
     NoDamage,
+    WrongPhase,
+    InvalidThrone,
+    Disconnected,
+    InvalidFormat,
+    ExcessDamage,
+    InconsistentPower { pos: Pos, side: usize },
+    RoomNotInShop,
+    InvalidShopIndex(usize),
+    InvalidRotation(u16),
+    UnsupportedVersion(u8),
 }
 
 impl fmt::Display for CastleError {
@@ -23,8 +34,26 @@ impl fmt::Display for CastleError {
             CastleError::NotNearlyOuterRoom => write!(f, "Room cannot be discarded because it is has too much connections."),
             CastleError::MustDiscard => write!(f, "Rooms must be discarded to match the damage."),
             CastleError::NoDamage => write!(f, "Room cannot be discarded because there is no damage."),
+            CastleError::InvalidThrone => write!(f, "Castle must contain exactly one throne room."),
+            CastleError::Disconnected => write!(f, "Castle has rooms that are not connected to the rest of the castle."),
+            CastleError::InvalidFormat => write!(f, "Input could not be parsed."),
+            CastleError::ExcessDamage => write!(f, "Castle cannot owe more damage than it has rooms to discard."),
+            CastleError::InconsistentPower { pos, side } => write!(f, "Room at {:?} needs power on side {} but its neighbor does not provide it.", pos, side),
+            CastleError::RoomNotInShop => write!(f, "Room cannot be placed because it does not match any room in the shop."),
+            CastleError::InvalidShopIndex(index) => write!(f, "Shop has no card at index {}.", index),
+            CastleError::InvalidRotation(rotation) => write!(f, "Room is turned by {} degrees instead of 0, 90, 180 or 270.", rotation),
+            CastleError::UnsupportedVersion(version) => write!(f, "Data is in format version {}, which is not supported.", version),
+            CastleError::WrongPhase => write!(f, "Action is not allowed in the current phase: rooms can only be built without damage and only discarded with damage."),
         }
     }
 }
 
 impl Error for CastleError {}
+
+impl From<RoomError> for CastleError {
+    fn from(error: RoomError) -> Self {
+        match error {
+            RoomError::InvalidRotation(rotation) => CastleError::InvalidRotation(rotation),
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{connecting, Castle, Connection, Pos, Result};
+use std::collections::{BTreeMap, BTreeSet};
+
+/*
+ * Read-only copy of a castle with everything a renderer asks for worked out
+ * once up front. It does not follow later changes to the castle it came from.
+ */
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FrozenCastle {
+    origin: Pos,
+    width: usize,
+    occupied: Vec<bool>,
+    connections: BTreeMap<Pos, [Connection; 4]>,
+    links: Vec<(Pos, Pos, Connection)>,
+    powered: BTreeSet<Pos>,
+    treasure: u32,
+}
+
+impl Castle {
+    /*
+     * Fails where get_links or get_treasure would, rather than freezing a
+     * castle with links or treasure missing
+     */
+    pub fn freeze(&self) -> Result<FrozenCastle> {
+        let ((min_x, min_y), (width, height)) = match self.bounding_box() {
+            Some(((min_x, min_y), (max_x, max_y))) => (
+                (min_x, min_y),
+                (
+                    (max_x as i16 - min_x as i16 + 1) as usize,
+                    (max_y as i16 - min_y as i16 + 1) as usize,
+                ),
+            ),
+            None => ((0, 0), (0, 0)),
+        };
+        let mut occupied = vec![false; width * height];
+        for (x, y) in self.rooms.keys() {
+            let index =
+                (*y as i16 - min_y as i16) as usize * width + (*x as i16 - min_x as i16) as usize;
+            occupied[index] = true;
+        }
+        let connections: BTreeMap<Pos, [Connection; 4]> = self
+            .rooms
+            .iter()
+            .map(|(pos, room)| (*pos, room.get_connections()))
+            .collect();
+        let mut links = Vec::new();
+        for (pos, sides) in connections.iter() {
+            for (i, con_pos) in connecting(*pos).iter().enumerate() {
+                if *con_pos <= *pos {
+                    continue;
+                }
+                if let Some(con_sides) = connections.get(con_pos) {
+                    match self.link(&sides[i], &con_sides[(i + 2) % 4])? {
+                        Connection::None => (),
+                        link => links.push((*pos, *con_pos, link)),
+                    }
+                }
+            }
+        }
+        let powered = self.powered_rooms().into_iter().collect();
+        Ok(FrozenCastle {
+            origin: (min_x, min_y),
+            width,
+            occupied,
+            connections,
+            links,
+            powered,
+            treasure: self.get_treasure()?,
+        })
+    }
+}
+
+impl FrozenCastle {
+    pub fn is_occupied(&self, pos: Pos) -> bool {
+        let x = pos.0 as i16 - self.origin.0 as i16;
+        let y = pos.1 as i16 - self.origin.1 as i16;
+        if x < 0 || y < 0 || x as usize >= self.width {
+            return false;
+        }
+        self.occupied
+            .get(y as usize * self.width + x as usize)
+            .copied()
+            .unwrap_or(false)
+    }
+    /*
+     * Connections of the room at pos after its rotation
+     */
+    pub fn connections(&self, pos: Pos) -> Option<[Connection; 4]> {
+        self.connections.get(&pos).copied()
+    }
+    /*
+     * Every link once, from the smaller position to the larger
+     */
+    pub fn links(&self) -> &[(Pos, Pos, Connection)] {
+        &self.links
+    }
+    pub fn is_powered(&self, pos: Pos) -> bool {
+        self.powered.contains(&pos)
+    }
+    pub fn treasure(&self) -> u32 {
+        self.treasure
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{empty_link_table, hall, room, throne};
+    use crate::{Action, Castle, CastleError, Connection, PlacedRoom};
+
+    #[test]
+    fn test_freeze() {
+        let vault = room(
+            "Vault",
+            2,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Cross(true),
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault.clone(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(vault, (0, -1), 270))
+            .unwrap();
+        let frozen = castle.freeze().unwrap();
+        assert_eq!(frozen.treasure(), castle.get_treasure().unwrap());
+        assert!(frozen.is_occupied((0, -1)));
+        assert!(!frozen.is_occupied((1, -1)));
+        assert!(!frozen.is_occupied((5, 5)));
+        assert_eq!(frozen.links().len(), 2);
+        assert!(frozen.is_powered((1, 0)));
+        assert_eq!(
+            frozen.connections((1, 0)),
+            Some(castle.rooms[&(1, 0)].get_connections())
+        );
+        assert_eq!(Castle::new_empty().freeze().unwrap().treasure(), 0);
+    }
+
+    #[test]
+    fn test_freeze_full_row() {
+        let mut castle = Castle::new(throne());
+        for x in (-128..=127).filter(|x| *x != 0) {
+            castle.rooms.insert((x, 0), PlacedRoom::from(hall(), 0));
+        }
+        let frozen = castle.freeze().unwrap();
+        assert!(frozen.is_occupied((-128, 0)));
+        assert!(frozen.is_occupied((127, 0)));
+        assert!(!frozen.is_occupied((0, 1)));
+    }
+
+    #[test]
+    fn test_freeze_bad_link_table() {
+        let castle = Castle::new(throne())
+            .apply(Action::Place(hall(), (1, 0), 0))
+            .unwrap()
+            .with_link_table(empty_link_table());
+        assert!(matches!(
+            castle.freeze(),
+            Err(CastleError::InvalidConnection)
+        ));
+    }
+}
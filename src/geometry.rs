@@ -0,0 +1,311 @@
+use crate::{Castle, CastleError, Connection, PlacedRoom, Pos, Result};
+use std::convert::TryFrom;
+
+impl Castle {
+    /*
+     * Inclusive (min, max) corners of the rooms, None for an empty castle
+     */
+    pub fn bounding_box(&self) -> Option<(Pos, Pos)> {
+        let mut positions = self.rooms.keys();
+        let first = *positions.next()?;
+        Some(positions.fold(
+            (first, first),
+            |((min_x, min_y), (max_x, max_y)), (x, y)| {
+                (
+                    (min_x.min(*x), min_y.min(*y)),
+                    (max_x.max(*x), max_y.max(*y)),
+                )
+            },
+        ))
+    }
+    /*
+     * (width, height) of the bounding box in cells, (0, 0) for an empty castle.
+     * A castle spanning all 256 columns or rows reports 255.
+     */
+    pub fn dimensions(&self) -> (u8, u8) {
+        match self.bounding_box() {
+            Some(((min_x, min_y), (max_x, max_y))) => {
+                let width = max_x as i16 - min_x as i16 + 1;
+                let height = max_y as i16 - min_y as i16 + 1;
+                (
+                    u8::try_from(width).unwrap_or(u8::MAX),
+                    u8::try_from(height).unwrap_or(u8::MAX),
+                )
+            }
+            None => (0, 0),
+        }
+    }
+    pub fn translate(&self, offset: Pos) -> Result<Castle> {
+        let (dx, dy) = offset;
+        let mut castle = self.clone();
+        castle.rooms.clear();
+        for ((x, y), room) in self.rooms.iter() {
+            let pos = match (x.checked_add(dx), y.checked_add(dy)) {
+                (Some(x), Some(y)) => (x, y),
+                _ => return Err(CastleError::InvalidPosition),
+            };
+            castle.rooms.insert(pos, room.clone());
+        }
+        Ok(castle)
+    }
+    /*
+     * The rooms within the inclusive rectangle from min to max, at their
+     * current positions and without damage. The piece may have no throne or
+     * be disconnected, so it can fail validate.
+     */
+    pub fn subcastle(&self, min: Pos, max: Pos) -> Castle {
+        let mut castle = self.clone();
+        castle.damage = 0;
+        castle
+            .rooms
+            .retain(|(x, y), _| min.0 <= *x && *x <= max.0 && min.1 <= *y && *y <= max.1);
+        castle
+    }
+    /*
+     * Number of rooms over the area of the bounding box, 1.0 for a filled rectangle
+     */
+    pub fn density(&self) -> f32 {
+        match self.bounding_box() {
+            Some(((min_x, min_y), (max_x, max_y))) => {
+                let width = max_x as i32 - min_x as i32 + 1;
+                let height = max_y as i32 - min_y as i32 + 1;
+                self.rooms.len() as f32 / (width * height) as f32
+            }
+            None => 0.0,
+        }
+    }
+    /*
+     * The same castle for every rotation, reflection and translation of its
+     * layout: the smallest of the eight turned and mirrored images, moved so
+     * the bounding box starts at (0, 0). Each room shows its sides with the
+     * lowest rotation it can, reflected only when turning alone can't. Fails
+     * with InvalidPosition when the castle is more than 128 cells wide or tall.
+     */
+    pub fn canonical_key(&self) -> Result<Castle> {
+        let images = (0..8)
+            .map(|image| {
+                let (mirrored, turns) = (image >= 4, image % 4);
+                let rooms: Vec<((i16, i16), PlacedRoom)> = self
+                    .rooms
+                    .iter()
+                    .map(|((x, y), room)| {
+                        let mut pos = (*x as i16, *y as i16);
+                        let mut sides = room.get_connections();
+                        if mirrored {
+                            pos.0 = -pos.0;
+                            sides.swap(1, 3);
+                        }
+                        for _ in 0..turns {
+                            pos = (-pos.1, pos.0);
+                            sides.rotate_right(1);
+                        }
+                        let unturned = PlacedRoom::from(room.info.clone(), 0);
+                        let room = [unturned.clone(), unturned.reflect()]
+                            .iter()
+                            .flat_map(|room| [0, 90, 180, 270].map(|rot| room.rotate(rot)))
+                            .find(|room| room.get_connections() == sides)
+                            .unwrap();
+                        (pos, room)
+                    })
+                    .collect();
+                let min_x = rooms.iter().map(|((x, _), _)| *x).min().unwrap_or(0);
+                let min_y = rooms.iter().map(|((_, y), _)| *y).min().unwrap_or(0);
+                let mut castle = self.clone();
+                castle.rooms = rooms
+                    .into_iter()
+                    .map(|((x, y), room)| {
+                        match (i8::try_from(x - min_x), i8::try_from(y - min_y)) {
+                            (Ok(x), Ok(y)) => Ok(((x, y), room)),
+                            _ => Err(CastleError::InvalidPosition),
+                        }
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(castle)
+            })
+            .collect::<Result<Vec<Castle>>>()?;
+        Ok(images.into_iter().min().unwrap())
+    }
+    /*
+     * Byte key equal for castles that only differ by where they sit on the
+     * grid: positions are taken relative to the throne (or the bounding box
+     * corner without one). Unlike canonical_key, turning or mirroring the
+     * castle gives a different key. The link table is not part of the key.
+     */
+    pub fn dedup_key(&self) -> Vec<u8> {
+        let origin = self
+            .rooms
+            .iter()
+            .find(|(_, room)| room.info.throne)
+            .map(|(pos, _)| *pos)
+            .or_else(|| self.bounding_box().map(|(min, _)| min))
+            .unwrap_or((0, 0));
+        let mut key = vec![self.damage];
+        for ((x, y), room) in self.rooms.iter() {
+            let dx = (*x as i16 - origin.0 as i16).to_le_bytes();
+            let dy = (*y as i16 - origin.1 as i16).to_le_bytes();
+            key.extend_from_slice(&dx);
+            key.extend_from_slice(&dy);
+            key.push(room.info.throne as u8);
+            key.push(room.info.treasure);
+            key.extend(room.get_connections().iter().map(connection_code));
+            key.extend_from_slice(room.info.name.as_bytes());
+            key.push(0);
+        }
+        key
+    }
+}
+
+pub(crate) fn connection_code(connection: &Connection) -> u8 {
+    match connection {
+        Connection::None => 0,
+        Connection::Wild => 1,
+        Connection::Diamond(power) => 2 + *power as u8,
+        Connection::Cross(power) => 4 + *power as u8,
+        Connection::Moon(power) => 6 + *power as u8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{room, small_vault, throne};
+    use crate::{Action, Castle, CastleError, Connection, PlacedRoom};
+
+    fn castle_at(positions: &[(i8, i8)]) -> Castle {
+        let mut castle = Castle::new(throne());
+        castle.rooms.clear();
+        for pos in positions {
+            castle.rooms.insert(*pos, PlacedRoom::from(throne(), 0));
+        }
+        castle
+    }
+
+    #[test]
+    fn test_density() {
+        assert_eq!(castle_at(&[(0, 0)]).density(), 1.0);
+        assert_eq!(castle_at(&[(0, 0), (1, 0), (0, 1), (1, 1)]).density(), 1.0);
+        assert_eq!(castle_at(&[(0, 0), (1, 0), (0, 1)]).density(), 0.75);
+        assert_eq!(castle_at(&[]).density(), 0.0);
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        assert_eq!(
+            castle_at(&[(0, 0), (-2, 1), (1, -3)]).bounding_box(),
+            Some(((-2, -3), (1, 1)))
+        );
+        assert_eq!(castle_at(&[]).bounding_box(), None);
+        assert_eq!(
+            castle_at(&[(3, -1)]).bounding_box(),
+            Some(((3, -1), (3, -1)))
+        );
+        let l_shape = castle_at(&[(0, 0), (0, 1), (1, 1)]);
+        assert_eq!(l_shape.bounding_box(), Some(((0, 0), (1, 1))));
+        assert_eq!(l_shape.dimensions(), (2, 2));
+    }
+
+    #[test]
+    fn test_translate() {
+        let mut castle = castle_at(&[(0, 0), (1, 0)]);
+        castle.damage = 1;
+        let moved = castle.translate((2, -3)).unwrap();
+        assert_eq!(
+            moved.rooms.keys().copied().collect::<Vec<_>>(),
+            vec![(2, -3), (3, -3)]
+        );
+        assert_eq!(moved.damage, 1);
+        assert_eq!(moved.rooms[&(2, -3)], castle.rooms[&(0, 0)]);
+        assert!(matches!(
+            castle.translate((127, 0)),
+            Err(CastleError::InvalidPosition)
+        ));
+    }
+
+    #[test]
+    fn test_subcastle() {
+        let mut castle = castle_at(&[(0, 0), (1, 0), (2, 0), (-1, 0), (2, 1)]);
+        castle.damage = 2;
+        let wing = castle.subcastle((1, -1), (3, 1));
+        assert_eq!(
+            wing.rooms.keys().copied().collect::<Vec<_>>(),
+            vec![(1, 0), (2, 0), (2, 1)]
+        );
+        assert_eq!(wing.damage, 0);
+        assert!(castle.subcastle((5, 5), (6, 6)).rooms.is_empty());
+    }
+
+    #[test]
+    fn test_dimensions() {
+        assert_eq!(castle_at(&[(0, 0)]).dimensions(), (1, 1));
+        assert_eq!(
+            castle_at(&[(0, 0), (0, 1), (0, 2), (1, 2)]).dimensions(),
+            (2, 3)
+        );
+        assert_eq!(castle_at(&[]).dimensions(), (0, 0));
+        assert_eq!(castle_at(&[(-128, 0), (127, 0)]).dimensions(), (255, 1));
+    }
+
+    #[test]
+    fn test_canonical_key() {
+        let vault = small_vault();
+        let east = Castle::new(throne())
+            .apply(Action::Place(vault.clone(), (1, 0), 0))
+            .unwrap();
+        let north = Castle::new(throne())
+            .apply(Action::Place(vault.clone(), (0, -1), 270))
+            .unwrap();
+        assert_eq!(
+            east.canonical_key().unwrap(),
+            north.canonical_key().unwrap()
+        );
+        assert_eq!(
+            east.canonical_key().unwrap(),
+            east.canonical_key().unwrap().canonical_key().unwrap()
+        );
+        let both = east.apply(Action::Place(vault, (0, -1), 270)).unwrap();
+        assert_ne!(both.canonical_key().unwrap(), east.canonical_key().unwrap());
+        // A tile no turn can mirror, linking west to the throne or, once
+        // reflected, east.
+        let chiral = room(
+            "Chiral",
+            0,
+            [
+                Connection::Moon(false),
+                Connection::Diamond(false),
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let mut west = Castle::new(throne());
+        west.rooms
+            .insert((-1, 0), PlacedRoom::from(chiral.clone(), 0).reflect());
+        let east = Castle::new(throne())
+            .apply(Action::Place(chiral, (1, 0), 0))
+            .unwrap();
+        assert!(west.validate().is_ok());
+        assert_eq!(west.canonical_key().unwrap(), east.canonical_key().unwrap());
+        assert!(matches!(
+            castle_at(&[(-100, 0), (100, 0)]).canonical_key(),
+            Err(CastleError::InvalidPosition)
+        ));
+    }
+
+    #[test]
+    fn test_dedup_key() {
+        let vault = small_vault();
+        let east = Castle::new(throne())
+            .apply(Action::Place(vault.clone(), (1, 0), 0))
+            .unwrap();
+        let north = Castle::new(throne())
+            .apply(Action::Place(vault, (0, -1), 270))
+            .unwrap();
+        assert_eq!(
+            east.dedup_key(),
+            east.translate((3, -2)).unwrap().dedup_key()
+        );
+        assert_ne!(east.dedup_key(), north.dedup_key());
+        assert_eq!(
+            east.canonical_key().unwrap(),
+            north.canonical_key().unwrap()
+        );
+    }
+}
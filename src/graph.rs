@@ -0,0 +1,601 @@
+use crate::{connecting, Castle, CastleError, Connection, Direction, Pos, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::convert::TryFrom;
+
+/*
+ * What lies along one side of a room: a link to the neighbor, a connection
+ * whose symbols don't match so nothing links, a wall between two rooms that
+ * touch without connecting, or the edge of the castle.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum EdgeKind {
+    OpenLink(Connection),
+    Unlinked,
+    Wall,
+    Boundary,
+}
+
+impl Castle {
+    /*
+     * Neighbors of pos whose facing sides connect to the room at pos
+     */
+    pub fn connected_neighbors(&self, pos: Pos) -> Vec<Pos> {
+        self.connected_neighbors_iter(pos).collect()
+    }
+    pub fn connected_neighbors_iter<'a>(&'a self, pos: Pos) -> impl Iterator<Item = Pos> + 'a {
+        let connections = self.rooms.get(&pos).map(|room| room.get_connections());
+        let neighbors = connecting(pos);
+        (0..4).filter_map(move |i| {
+            let connections = connections?;
+            let con_room = self.rooms.get(&neighbors[i])?;
+            if connections[i].connect(&con_room.get_connections()[(i + 2) % 4]) == Some(true) {
+                Some(neighbors[i])
+            } else {
+                None
+            }
+        })
+    }
+    /*
+     * Whether every room can be reached from the throne through connected sides
+     */
+    pub fn is_connected(&self) -> bool {
+        match self.rooms.iter().find(|(_, room)| room.info.throne) {
+            Some((throne, _)) => self.reachable_from(*throne).len() == self.rooms.len(),
+            None => false,
+        }
+    }
+    /*
+     * Positions that cannot be reached from the throne through connected
+     * sides, in position order. Without a throne every position is reported.
+     */
+    pub fn disconnected_positions(&self) -> Vec<Pos> {
+        let reached = match self.rooms.iter().find(|(_, room)| room.info.throne) {
+            Some((throne, _)) => self.reachable_from(*throne),
+            None => HashSet::new(),
+        };
+        self.rooms
+            .keys()
+            .filter(|pos| !reached.contains(pos))
+            .copied()
+            .collect()
+    }
+    /*
+     * Every room side with what lies along it. A side shared by two rooms is
+     * only given once, from the room with the smaller position, and the link
+     * is the one seen from that room.
+     */
+    pub fn edges(&self) -> impl Iterator<Item = (Pos, Direction, EdgeKind)> + '_ {
+        self.rooms.iter().flat_map(move |(pos, room)| {
+            let connections = room.get_connections();
+            let neighbors = connecting(*pos);
+            Direction::ALL.iter().filter_map(move |dir| {
+                let i = dir.index();
+                let kind = match self.rooms.get(&neighbors[i]) {
+                    None => EdgeKind::Boundary,
+                    Some(_) if neighbors[i] < *pos => return None,
+                    Some(con_room) => {
+                        let con_side = con_room.get_connections()[(i + 2) % 4];
+                        if connections[i].connect(&con_side) != Some(true) {
+                            EdgeKind::Wall
+                        } else {
+                            match self.link(&connections[i], &con_side) {
+                                Ok(Connection::None) | Err(_) => EdgeKind::Unlinked,
+                                Ok(link) => EdgeKind::OpenLink(link),
+                            }
+                        }
+                    }
+                };
+                Some((*pos, *dir, kind))
+            })
+        })
+    }
+    /*
+     * Sides of rooms with a connection that faces an empty position
+     */
+    pub fn open_sides(&self) -> Vec<(Pos, Direction)> {
+        let mut open = Vec::new();
+        for (pos, room) in self.rooms.iter() {
+            let connections = room.get_connections();
+            for (dir, con_pos) in Direction::ALL.iter().zip(connecting(*pos).iter()) {
+                if connections[dir.index()] != Connection::None && !self.rooms.contains_key(con_pos)
+                {
+                    open.push((*pos, *dir));
+                }
+            }
+        }
+        open
+    }
+    pub fn open_sides_facing(&self, dir: Direction) -> Vec<Pos> {
+        self.open_sides()
+            .into_iter()
+            .filter(|(_, side)| *side == dir)
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+    /*
+     * Every room with the neighbors it is connected to, and the link symbol
+     * seen from the room's side of the connection.
+     */
+    pub fn adjacency_list(&self) -> BTreeMap<Pos, Vec<(Pos, Connection)>> {
+        let mut adjacency = BTreeMap::new();
+        for (pos, room) in self.rooms.iter() {
+            let mut neighbors = Vec::new();
+            for (i, con_pos) in connecting(*pos).iter().enumerate() {
+                if let Some(con_room) = self.rooms.get(con_pos) {
+                    let side = room.get_connections()[i];
+                    let con_side = con_room.get_connections()[(i + 2) % 4];
+                    if side.connect(&con_side) == Some(true) {
+                        if let Ok(link) = self.link(&side, &con_side) {
+                            neighbors.push((*con_pos, link));
+                        }
+                    }
+                }
+            }
+            adjacency.insert(*pos, neighbors);
+        }
+        adjacency
+    }
+    /*
+     * Fraction of all real (non None) room sides that connect to a neighbor
+     */
+    pub fn connection_utilization(&self) -> f32 {
+        let mut sides = 0;
+        let mut linked = 0;
+        for (pos, room) in self.rooms.iter() {
+            for (i, con_pos) in connecting(*pos).iter().enumerate() {
+                let side = room.get_connections()[i];
+                if matches!(side, Connection::None) {
+                    continue;
+                }
+                sides += 1;
+                if let Some(con_room) = self.rooms.get(con_pos) {
+                    if side.connect(&con_room.get_connections()[(i + 2) % 4]) == Some(true) {
+                        linked += 1;
+                    }
+                }
+            }
+        }
+        if sides == 0 {
+            0.0
+        } else {
+            linked as f32 / sides as f32
+        }
+    }
+    /*
+     * Non-throne rooms that cannot be moved, with the number of neighbors they
+     * are connected to. Only rooms connected to exactly one neighbor can move.
+     */
+    pub fn immovable_rooms(&self) -> Vec<(Pos, u8)> {
+        self.rooms
+            .iter()
+            .filter(|(_, room)| !room.info.throne)
+            .map(|(pos, _)| (*pos, self.room_num_connected(*pos).unwrap()))
+            .filter(|(_, degree)| *degree != 1)
+            .collect()
+    }
+    /*
+     * How many rooms have to be discarded, one at a time, before the throne is
+     * left with at most one connected neighbor. Rooms are peeled greedily,
+     * always taking the discardable room nearest the throne, so the count is
+     * an upper bound on the fewest discards rather than an exact minimum. 0
+     * when there is no throne or it is already exposed. When no discard is
+     * left before the throne is exposed, every other room is counted.
+     */
+    pub fn throne_protection(&self) -> Result<u8> {
+        let throne = match self.rooms.iter().find(|(_, r)| r.info.throne) {
+            Some((pos, _)) => *pos,
+            None => return Ok(0),
+        };
+        let mut castle = self.clone();
+        castle.damage = 0;
+        let mut removed = 0u8;
+        while castle.room_num_connected(throne)? > 1 {
+            let distance: BTreeMap<Pos, usize> = castle
+                .rings_from_throne()?
+                .into_iter()
+                .enumerate()
+                .flat_map(|(ring, positions)| positions.into_iter().map(move |pos| (pos, ring)))
+                .collect();
+            match castle
+                .possible_discard()
+                .into_iter()
+                .min_by_key(|pos| (distance[pos], *pos))
+            {
+                Some(pos) => {
+                    castle.rooms.remove(&pos);
+                    removed = removed.saturating_add(1);
+                }
+                None => return Ok(u8::try_from(self.rooms.len() - 1).unwrap_or(u8::MAX)),
+            }
+        }
+        Ok(removed)
+    }
+    /*
+     * Rooms grouped by how many connections away from the throne they are,
+     * starting with the throne itself. Rooms that cannot be reached from the
+     * throne are collected in one extra bucket at the end.
+     */
+    pub fn rings_from_throne(&self) -> Result<Vec<Vec<Pos>>> {
+        let throne = self
+            .rooms
+            .iter()
+            .find(|(_, r)| r.info.throne)
+            .map(|(pos, _)| *pos)
+            .ok_or(CastleError::InvalidThrone)?;
+        let adjacency = self.adjacency_list();
+        let mut seen = BTreeSet::new();
+        seen.insert(throne);
+        let mut rings = vec![vec![throne]];
+        loop {
+            let mut next = BTreeSet::new();
+            for pos in rings.last().unwrap() {
+                for (con_pos, _) in adjacency[pos].iter() {
+                    if seen.insert(*con_pos) {
+                        next.insert(*con_pos);
+                    }
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            rings.push(next.into_iter().collect());
+        }
+        let disconnected: Vec<Pos> = self
+            .rooms
+            .keys()
+            .filter(|pos| !seen.contains(pos))
+            .copied()
+            .collect();
+        if !disconnected.is_empty() {
+            rings.push(disconnected);
+        }
+        Ok(rings)
+    }
+    /*
+     * Rooms whose removal would split the connected rooms around them into
+     * separate groups, found with the DFS lowlink algorithm.
+     */
+    pub fn articulation_points(&self) -> BTreeSet<Pos> {
+        let adjacency = self.adjacency_list();
+        let mut order = BTreeMap::new();
+        let mut low = BTreeMap::new();
+        let mut points = BTreeSet::new();
+        for start in self.rooms.keys() {
+            if !order.contains_key(start) {
+                lowlink(*start, None, &adjacency, &mut order, &mut low, &mut points);
+            }
+        }
+        points
+    }
+    /*
+     * Node ids are indices into the sorted position list. Each edge appears
+     * once, from the lower to the higher id.
+     */
+    pub fn indexed_graph(&self) -> (Vec<Pos>, Vec<(usize, usize, Connection)>) {
+        let nodes: Vec<Pos> = self.rooms.keys().copied().collect();
+        let ids: BTreeMap<Pos, usize> = nodes.iter().enumerate().map(|(i, p)| (*p, i)).collect();
+        let mut edges = Vec::new();
+        for (pos, neighbors) in self.adjacency_list() {
+            for (con_pos, link) in neighbors {
+                if pos < con_pos {
+                    edges.push((ids[&pos], ids[&con_pos], link));
+                }
+            }
+        }
+        (nodes, edges)
+    }
+}
+
+fn lowlink(
+    pos: Pos,
+    parent: Option<Pos>,
+    adjacency: &BTreeMap<Pos, Vec<(Pos, Connection)>>,
+    order: &mut BTreeMap<Pos, usize>,
+    low: &mut BTreeMap<Pos, usize>,
+    points: &mut BTreeSet<Pos>,
+) {
+    let index = order.len();
+    order.insert(pos, index);
+    low.insert(pos, index);
+    let mut children = 0;
+    for (next, _) in adjacency[&pos].iter() {
+        if Some(*next) == parent {
+            continue;
+        }
+        if let Some(next_index) = order.get(next) {
+            let next_index = *next_index;
+            low.insert(pos, low[&pos].min(next_index));
+        } else {
+            children += 1;
+            lowlink(*next, Some(pos), adjacency, order, low, points);
+            low.insert(pos, low[&pos].min(low[next]));
+            if parent.is_some() && low[next] >= index {
+                points.insert(pos);
+            }
+        }
+    }
+    if parent.is_none() && children > 1 {
+        points.insert(pos);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{hall, room, small_vault, throne};
+    use crate::{Action, Castle, Connection, Direction, EdgeKind, PlacedRoom};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_is_connected() {
+        let vault = small_vault();
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault.clone(), (1, 0), 0))
+            .unwrap();
+        assert!(castle.is_connected());
+
+        let mut islands = Castle::new(throne());
+        islands.rooms.insert((3, 0), PlacedRoom::from(vault, 0));
+        assert!(!islands.is_connected());
+    }
+
+    #[test]
+    fn test_disconnected_positions() {
+        let corridor = room(
+            "Corridor",
+            0,
+            [
+                Connection::None,
+                Connection::Cross(false),
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let mut castle = Castle::new(throne())
+            .apply(Action::Place(corridor.clone(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(corridor.clone(), (2, 0), 0))
+            .unwrap()
+            .apply(Action::Place(corridor, (3, 0), 0))
+            .unwrap();
+        assert!(castle.disconnected_positions().is_empty());
+        castle.rooms.remove(&(1, 0));
+        assert_eq!(castle.disconnected_positions(), vec![(2, 0), (3, 0)]);
+        castle.rooms.remove(&(0, 0));
+        assert_eq!(castle.disconnected_positions(), vec![(2, 0), (3, 0)]);
+        assert!(Castle::new_empty().disconnected_positions().is_empty());
+    }
+
+    #[test]
+    fn test_edges() {
+        let vault = small_vault();
+        let mut castle = Castle::new(throne())
+            .apply(Action::Place(vault.clone(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(vault, (0, 1), 90))
+            .unwrap();
+        castle.rooms.insert(
+            (1, 1),
+            PlacedRoom::from(room("Rubble", 0, [Connection::None; 4]), 0),
+        );
+        let edges: Vec<_> = castle.edges().collect();
+        let links: Vec<_> = edges
+            .iter()
+            .filter(|(_, _, kind)| matches!(kind, EdgeKind::OpenLink(_)))
+            .collect();
+        assert_eq!(links.len(), 2);
+        assert!(links.contains(&&(
+            (0, 0),
+            Direction::East,
+            EdgeKind::OpenLink(Connection::Cross(true))
+        )));
+        assert_eq!(
+            edges
+                .iter()
+                .filter(|(_, _, kind)| *kind == EdgeKind::Wall)
+                .count(),
+            2
+        );
+        assert_eq!(
+            edges
+                .iter()
+                .filter(|(_, _, kind)| *kind == EdgeKind::Boundary)
+                .count(),
+            8
+        );
+
+        // Diamond against cross still joins the rooms, it just links nothing.
+        let diamond = room(
+            "Diamond Vault",
+            1,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Diamond(false),
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(hall(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(diamond, (2, 0), 0))
+            .unwrap();
+        assert!(castle.is_connected());
+        assert!(castle
+            .edges()
+            .any(|edge| edge == ((1, 0), Direction::East, EdgeKind::Unlinked)));
+        assert!(castle.edges().all(|(_, _, kind)| kind != EdgeKind::Wall));
+    }
+
+    #[test]
+    fn test_open_sides_facing() {
+        let hall = hall();
+        let tower = room(
+            "Tower",
+            0,
+            [
+                Connection::Moon(false),
+                Connection::Cross(false),
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(hall, (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(tower, (2, 0), 0))
+            .unwrap();
+        assert_eq!(
+            castle.open_sides_facing(Direction::North),
+            vec![(0, 0), (2, 0)]
+        );
+        assert_eq!(castle.open_sides_facing(Direction::East), vec![(2, 0)]);
+        assert_eq!(castle.open_sides_facing(Direction::West), vec![(0, 0)]);
+        assert_eq!(castle.open_sides().len(), 5);
+    }
+
+    #[test]
+    fn test_indexed_graph() {
+        let vault = room(
+            "Small Vault",
+            1,
+            [
+                Connection::None,
+                Connection::Diamond(false),
+                Connection::None,
+                Connection::None,
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault, (-1, 0), 0))
+            .unwrap();
+        let (nodes, edges) = castle.indexed_graph();
+        assert_eq!(nodes, vec![(-1, 0), (0, 0)]);
+        assert_eq!(edges, vec![(0, 1, Connection::Diamond(false))]);
+        for (a, b, _) in edges {
+            assert!(a < nodes.len() && b < nodes.len());
+        }
+    }
+
+    #[test]
+    fn test_connection_utilization() {
+        let vault = small_vault();
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault, (1, 0), 0))
+            .unwrap();
+        // Two linked sides out of the throne's four and the vault's one.
+        assert_eq!(castle.connection_utilization(), 0.4);
+
+        let walls = room("Closet", 0, [Connection::None; 4]);
+        assert_eq!(Castle::new(walls).connection_utilization(), 0.0);
+    }
+
+    #[test]
+    fn test_throne_protection() {
+        assert_eq!(Castle::new(throne()).throne_protection().unwrap(), 0);
+
+        let hall = hall();
+        let mut castle = Castle::new(throne());
+        for pos in [(1, 0), (2, 0), (-1, 0), (-2, 0)] {
+            castle = castle.apply(Action::Place(hall.clone(), pos, 0)).unwrap();
+        }
+        assert_eq!(castle.throne_protection().unwrap(), 2);
+
+        // Every neighbor is outer, but the throne is only exposed once three
+        // of the four have gone.
+        let vault = small_vault();
+        let mut castle = Castle::new(throne());
+        for (pos, rotation) in [((1, 0), 0), ((0, 1), 90), ((-1, 0), 180), ((0, -1), 270)] {
+            castle = castle
+                .apply(Action::Place(vault.clone(), pos, rotation))
+                .unwrap();
+        }
+        assert_eq!(castle.throne_protection().unwrap(), 3);
+
+        // A full seven by seven grid, far too big to try every order of
+        // discards. The throne is buried, but fewer than all rooms must go.
+        let hub = room("Hub", 0, [Connection::Cross(false); 4]);
+        let mut castle = Castle::new(throne());
+        for x in -3..=3 {
+            for y in -3..=3 {
+                if (x, y) != (0, 0) {
+                    castle
+                        .rooms
+                        .insert((x, y), PlacedRoom::from(hub.clone(), 0));
+                }
+            }
+        }
+        let protection = castle.throne_protection().unwrap();
+        assert!(protection > 3 && protection < 48);
+    }
+
+    #[test]
+    fn test_immovable_rooms() {
+        let hub = room("Hub", 0, [Connection::Cross(false); 4]);
+        let vault = small_vault();
+        // A plus shape around the hub, with the throne as its western tip.
+        let castle = Castle::new(throne())
+            .apply(Action::Place(hub, (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(vault.clone(), (2, 0), 0))
+            .unwrap()
+            .apply(Action::Place(vault.clone(), (1, -1), 270))
+            .unwrap()
+            .apply(Action::Place(vault, (1, 1), 90))
+            .unwrap();
+        assert_eq!(castle.immovable_rooms(), vec![((1, 0), 4)]);
+        for tip in [(0, 0), (2, 0), (1, -1), (1, 1)] {
+            assert!(castle.is_outer(tip).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_rings_from_throne() {
+        let hall = hall();
+        let mut castle = Castle::new(throne());
+        for pos in [(1, 0), (2, 0), (3, 0)] {
+            castle = castle.apply(Action::Place(hall.clone(), pos, 0)).unwrap();
+        }
+        assert_eq!(
+            castle.rings_from_throne().unwrap(),
+            vec![vec![(0, 0)], vec![(1, 0)], vec![(2, 0)], vec![(3, 0)]]
+        );
+        castle.rooms.insert((5, 5), PlacedRoom::from(hall, 0));
+        assert_eq!(
+            castle.rings_from_throne().unwrap().last().unwrap(),
+            &vec![(5, 5)]
+        );
+    }
+
+    #[test]
+    fn test_articulation_points() {
+        let hub = room("Hub", 0, [Connection::Cross(false); 4]);
+        let mut castle = Castle::new(throne());
+        // Two loops meeting at the throne, and a leaf hanging off the first one.
+        for pos in [(1, 0), (1, 1), (0, 1), (-1, 0), (-1, -1), (0, -1), (2, 0)] {
+            castle.rooms.insert(pos, PlacedRoom::from(hub.clone(), 0));
+        }
+        let points = castle.articulation_points();
+        assert!(points.contains(&(0, 0)));
+        assert!(!points.contains(&(2, 0)));
+        assert_eq!(points.into_iter().collect::<Vec<_>>(), vec![(0, 0), (1, 0)]);
+    }
+
+    #[test]
+    fn test_connected_neighbors_iter() {
+        let hub = room("Hub", 0, [Connection::Cross(false); 4]);
+        let wall = room("Wall", 0, [Connection::None; 4]);
+        let mut castle = Castle::new(throne());
+        for pos in [(1, 0), (0, 1), (1, 1)] {
+            castle.rooms.insert(pos, PlacedRoom::from(hub.clone(), 0));
+        }
+        castle.rooms.insert((-1, 0), PlacedRoom::from(wall, 0));
+        for pos in [(0, 0), (1, 0), (1, 1), (-1, 0), (7, 7)] {
+            let lazy: BTreeSet<_> = castle.connected_neighbors_iter(pos).collect();
+            let eager: BTreeSet<_> = castle.connected_neighbors(pos).into_iter().collect();
+            assert_eq!(lazy, eager);
+        }
+        assert_eq!(castle.connected_neighbors((0, 0)), vec![(1, 0), (0, 1)]);
+        assert!(castle.connected_neighbors((-1, 0)).is_empty());
+    }
+}
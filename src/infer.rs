@@ -0,0 +1,92 @@
+use crate::{Action, Castle, CastleError, Pos, Result, Room};
+
+impl Castle {
+    /*
+     * Turns a UI gesture into a legal action: a card dropped from the shop is
+     * a Place, a room dragged onto another room is a Swap and a room dragged
+     * onto an empty cell is a Move. Place and Move pick the first rotation
+     * that fits; if none does, the error for rotation 0 is returned.
+     */
+    pub fn infer_action(
+        &self,
+        from: Option<Pos>,
+        to: Pos,
+        shop_index: Option<usize>,
+        shop: &[Room],
+    ) -> Result<Action> {
+        let candidates: Vec<Action> = match (from, shop_index) {
+            (None, Some(index)) => {
+                let room = shop
+                    .get(index)
+                    .ok_or(CastleError::InvalidShopIndex(index))?;
+                [0, 90, 180, 270]
+                    .iter()
+                    .map(|rot| Action::Place(room.clone(), to, *rot))
+                    .collect()
+            }
+            (Some(from), None) if self.rooms.contains_key(&to) => vec![Action::Swap(from, to)],
+            (Some(from), None) => [0, 90, 180, 270]
+                .iter()
+                .map(|rot| Action::Move(from, to, *rot))
+                .collect(),
+            _ => return Err(CastleError::InvalidFormat),
+        };
+        let mut first_error = None;
+        for action in candidates {
+            match self.apply(action.clone()) {
+                Ok(_) => return Ok(action),
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        Err(first_error.unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{hall, small_vault, throne};
+    use crate::{Action, Castle, CastleError};
+
+    #[test]
+    fn test_infer_place() {
+        let castle = Castle::new(throne());
+        let shop = [small_vault()];
+        let action = castle.infer_action(None, (0, 1), Some(0), &shop).unwrap();
+        assert!(matches!(action, Action::Place(_, (0, 1), 90)));
+        assert!(matches!(
+            castle.infer_action(None, (0, 1), Some(1), &shop),
+            Err(CastleError::InvalidShopIndex(1))
+        ));
+    }
+
+    #[test]
+    fn test_infer_move() {
+        let castle = Castle::new(throne())
+            .apply(Action::Place(small_vault(), (1, 0), 0))
+            .unwrap();
+        let action = castle
+            .infer_action(Some((1, 0)), (0, -1), None, &[])
+            .unwrap();
+        assert!(matches!(action, Action::Move((1, 0), (0, -1), 270)));
+    }
+
+    #[test]
+    fn test_infer_swap() {
+        let hall = hall();
+        let castle = Castle::new(throne())
+            .apply(Action::Place(hall.clone(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(hall, (-1, 0), 0))
+            .unwrap();
+        let action = castle
+            .infer_action(Some((1, 0)), (-1, 0), None, &[])
+            .unwrap();
+        assert!(matches!(action, Action::Swap((1, 0), (-1, 0))));
+        assert!(matches!(
+            castle.infer_action(Some((1, 0)), (2, 0), Some(0), &[]),
+            Err(CastleError::InvalidFormat)
+        ));
+    }
+}
@@ -1,14 +1,49 @@
+mod bytes;
+#[cfg(feature = "compact-actions")]
+mod compact;
+mod damage;
+mod discard;
 mod error;
+mod frozen;
+mod geometry;
+mod graph;
+mod infer;
+mod link_table;
+mod metrics;
+mod power;
+mod render;
+mod replay;
 mod room;
+#[cfg(feature = "rooms-vec")]
+mod rooms_vec;
+mod score;
+mod search;
+mod snapshot;
+mod summary;
 
+#[cfg(feature = "compact-actions")]
+pub use compact::CompactAction;
+pub use damage::{DamageBreakdown, DamageOutcome};
+pub use discard::{DiscardImpact, DiscardPolicy, LeastValuable, PreserveTreasure};
 pub use error::CastleError;
-pub use room::{connection::Connection, Room};
+pub use frozen::FrozenCastle;
+pub use graph::EdgeKind;
+pub use link_table::LinkTable;
+pub use metrics::CastleMetrics;
+pub use power::PowerModel;
+pub use render::RenderOptions;
+pub use replay::{GameLog, LogStats};
+pub use room::{connection::Connection, error::RoomError, Room};
+pub use score::ScoreConfig;
+pub use snapshot::CastleSnapshot;
+pub use summary::{CastleSummary, Objective};
 
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{BTreeMap, HashSet},
+    convert::TryFrom,
     hash::Hash,
-    result,
+    mem, result,
 };
 
 type Result<T> = result::Result<T, CastleError>;
@@ -16,10 +51,43 @@ type Result<T> = result::Result<T, CastleError>;
 pub type Pos = (i8, i8);
 pub type Rot = u16;
 
+/*
+ * Sides of a room, in the same order as its connections and connecting()
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ];
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+    pub fn opposite(&self) -> Direction {
+        Direction::ALL[(self.index() + 2) % 4]
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug, Ord, PartialOrd)]
 pub struct PlacedRoom {
     pub info: Room,
     pub rotation: Rot,
+    /*
+     * Actions carry no reflection: Place always lays a room down unreflected,
+     * while Move and Swap keep whatever the room already has. A reflected room
+     * only gets into a castle by inserting it directly or loading a castle.
+     */
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub reflected: bool,
 }
 
 impl PlacedRoom {
@@ -27,23 +95,53 @@ impl PlacedRoom {
         Self {
             info: room,
             rotation,
+            reflected: false,
         }
     }
     pub fn rotate(&self, rotation: Rot) -> Self {
         Self {
             info: self.info.clone(),
             rotation,
+            reflected: self.reflected,
+        }
+    }
+    /*
+     * Flips the tile over, swapping its east and west sides before it is rotated
+     */
+    pub fn reflect(&self) -> Self {
+        Self {
+            info: self.info.clone(),
+            rotation: self.rotation,
+            reflected: !self.reflected,
         }
     }
+    /*
+     * Like rotate, but only accepts 0, 90, 180 or 270 degrees
+     */
+    pub fn try_rotate(&self, rotation: Rot) -> result::Result<Self, RoomError> {
+        self.info.get_rotated_connections_checked(rotation)?;
+        Ok(self.rotate(rotation))
+    }
     pub fn get_connections(&self) -> [Connection; 4] {
-        self.info.get_rotated_connections(self.rotation)
+        if !self.reflected {
+            return self.info.get_rotated_connections(self.rotation);
+        }
+        // Mirroring then turning one way is turning the other way then mirroring.
+        let [north, east, south, west] = self
+            .info
+            .get_rotated_connections((360 - self.rotation % 360) % 360);
+        [north, west, south, east]
     }
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct Castle {
+    #[cfg_attr(feature = "rooms-vec", serde(with = "rooms_vec"))]
     pub rooms: BTreeMap<Pos, PlacedRoom>,
+    #[serde(default, skip_serializing_if = "is_zero")]
     pub damage: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_table: Option<LinkTable>,
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Ord, PartialOrd, Serialize, Deserialize)]
@@ -55,121 +153,336 @@ pub enum Action {
     Damage(u8, u8, u8),
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum ActionKind {
+    Place,
+    Move,
+    Swap,
+    Discard,
+    Damage,
+}
+
+impl Action {
+    pub fn kind(&self) -> ActionKind {
+        match self {
+            Action::Place(..) => ActionKind::Place,
+            Action::Move(..) => ActionKind::Move,
+            Action::Swap(..) => ActionKind::Swap,
+            Action::Discard(..) => ActionKind::Discard,
+            Action::Damage(..) => ActionKind::Damage,
+        }
+    }
+}
+
+/*
+ * Build: no damage is owed, rooms can be placed, moved or swapped.
+ * Resolve: damage is owed and must be paid off by discarding rooms.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum Phase {
+    Build,
+    Resolve,
+}
+
+/*
+ * Strict: every side touching a neighbor must connect.
+ * Lenient: real sides may face a neighbor's wall, as long as one side connects.
+ */
+#[derive(
+    Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Ord, PartialOrd, Serialize, Deserialize,
+)]
+pub enum PlacementRule {
+    #[default]
+    Strict,
+    Lenient,
+}
+
 impl Castle {
     pub fn new(starting_room: Room) -> Castle {
         let mut rooms = BTreeMap::new();
         rooms.insert((0, 0), PlacedRoom::from(starting_room, 0));
-        Castle { rooms, damage: 0 }
+        Castle {
+            rooms,
+            damage: 0,
+            link_table: None,
+        }
+    }
+    /*
+     * A castle without any room. Its first room can be placed at any position.
+     */
+    pub fn new_empty() -> Castle {
+        Castle {
+            rooms: BTreeMap::new(),
+            damage: 0,
+            link_table: None,
+        }
+    }
+    /*
+     * A one room castle that already owes damage, at most one discard. One
+     * damage covers the only room, so that castle is already lost.
+     */
+    pub fn new_with_damage(starting_room: Room, damage: u8) -> Result<Castle> {
+        if damage > 1 {
+            return Err(CastleError::ExcessDamage);
+        }
+        let mut castle = Castle::new(starting_room);
+        castle.damage = damage;
+        Ok(castle)
+    }
+    pub fn phase(&self) -> Phase {
+        if self.damage > 0 {
+            Phase::Resolve
+        } else {
+            Phase::Build
+        }
     }
     pub fn is_lost(&self) -> bool {
         self.damage as usize >= self.rooms.values().len()
             || self.rooms.values().all(|v| !v.info.throne)
     }
-    pub fn get_links(&self) -> (u8, u8, u8, u8) {
+    /*
+     * A room is outer when exactly one neighbor is connected to it. Only outer
+     * rooms can be moved, and they are the first to be discarded.
+     */
+    pub fn is_outer(&self, pos: Pos) -> Result<bool> {
+        self.room_is_outer(pos)
+    }
+    /*
+     * Number of neighbors connected to the room at pos
+     */
+    pub fn connection_degree(&self, pos: Pos) -> Result<u8> {
+        self.room_num_connected(pos)
+    }
+    /*
+     * Checks that every room is turned by a quarter turn, that the castle has
+     * exactly one throne, that every pair of adjacent rooms has matching sides
+     * and that all rooms are connected.
+     */
+    pub fn validate(&self) -> Result<()> {
+        for room in self.rooms.values() {
+            room.try_rotate(room.rotation)?;
+        }
+        if self.rooms.values().filter(|r| r.info.throne).count() != 1 {
+            return Err(CastleError::InvalidThrone);
+        }
+        for (pos, room) in self.rooms.iter() {
+            for (i, con_pos) in connecting(*pos).iter().enumerate() {
+                if let Some(con_room) = self.rooms.get(con_pos) {
+                    if room.get_connections()[i].connect(&con_room.get_connections()[(i + 2) % 4])
+                        == Some(false)
+                    {
+                        return Err(CastleError::InvalidConnection);
+                    }
+                }
+            }
+        }
+        let start = self.rooms.keys().next().unwrap();
+        if self.reachable_from(*start).len() < self.rooms.len() {
+            return Err(CastleError::Disconnected);
+        }
+        Ok(())
+    }
+    /*
+     * No damage is owed and nothing is broken, so the turn can be passed on.
+     */
+    pub fn is_stable(&self) -> bool {
+        self.damage == 0 && self.validate().is_ok()
+    }
+    pub fn get_links(&self) -> Result<(u8, u8, u8, u8)> {
+        self.links_where(|_| true)
+    }
+    /*
+     * All links of every kind together, saturating at u8::MAX
+     */
+    pub fn total_links(&self) -> Result<u8> {
+        let (diamond, cross, moon, wild) = self.get_links()?;
+        Ok(diamond
+            .saturating_add(cross)
+            .saturating_add(moon)
+            .saturating_add(wild))
+    }
+    /*
+     * Sum over the four link kinds of how far the castle is from target
+     */
+    pub fn link_distance(&self, target: (u8, u8, u8, u8)) -> Result<u16> {
+        let (diamond, cross, moon, wild) = self.get_links()?;
+        let distance = |a: u8, b: u8| (a as i16 - b as i16).unsigned_abs();
+        Ok(distance(diamond, target.0)
+            + distance(cross, target.1)
+            + distance(moon, target.2)
+            + distance(wild, target.3))
+    }
+    /*
+     * Links within the inclusive rectangle from min to max. Links to rooms
+     * outside of it are not counted.
+     */
+    pub fn links_in_region(&self, min: Pos, max: Pos) -> Result<(u8, u8, u8, u8)> {
+        if min.0 > max.0 || min.1 > max.1 {
+            return Err(CastleError::InvalidPosition);
+        }
+        self.links_where(|(x, y)| min.0 <= x && x <= max.0 && min.1 <= y && y <= max.1)
+    }
+    /*
+     * Links between two rooms that both pass include
+     */
+    fn links_where(&self, include: impl Fn(Pos) -> bool) -> Result<(u8, u8, u8, u8)> {
         let mut diamond = 0;
         let mut cross = 0;
         let mut moon = 0;
         let mut wild = 0;
-        for (pos, room) in self.rooms.iter() {
+        for (pos, room) in self.rooms.iter().filter(|(pos, _)| include(**pos)) {
             for (i, con_pos) in connecting(*pos).iter().enumerate() {
-                if let Some(con_room) = self.rooms.get(&con_pos) {
-                    if let Ok(link) =
-                        room.get_connections()[i].link(&con_room.get_connections()[(i + 2) % 4])
-                    {
-                        match link {
-                            Connection::Wild => wild += 1,
-                            Connection::Diamond(_) => diamond += 1,
-                            Connection::Cross(_) => cross += 1,
-                            Connection::Moon(_) => moon += 1,
-                            Connection::None => (),
-                        }
-                    } else {
-                        panic!("Castle has incorrectly placed room");
+                if !include(*con_pos) {
+                    continue;
+                }
+                if let Some(con_room) = self.rooms.get(con_pos) {
+                    match self.link(
+                        &room.get_connections()[i],
+                        &con_room.get_connections()[(i + 2) % 4],
+                    )? {
+                        Connection::Wild => wild += 1,
+                        Connection::Diamond(_) => diamond += 1,
+                        Connection::Cross(_) => cross += 1,
+                        Connection::Moon(_) => moon += 1,
+                        Connection::None => (),
                     }
                 }
             }
         }
         // Because we count all links twice, we need to divide by 2
-        (diamond / 2, cross / 2, moon / 2, wild / 2)
+        Ok((diamond / 2, cross / 2, moon / 2, wild / 2))
     }
-    pub fn get_treasure(&self) -> u8 {
-        let mut treasure = 0;
-        for (pos, room) in self.rooms.iter() {
-            if room.info.treasure > 0 && self.room_is_powered(*pos).unwrap() {
-                treasure += room.info.treasure;
-            }
-        }
-        treasure
+    /*
+     * Treasure of every room power reaches, see powered_rooms. Power starts at
+     * wild sides and crosses any link where either side carries power, so a
+     * room's own powered sides don't have to face anything.
+     */
+    pub fn get_treasure(&self) -> Result<u32> {
+        self.get_treasure_with(PowerModel::Propagated)
+    }
+    /*
+     * Rough bytes held by the castle: the struct, each room entry with an
+     * allowance for the map's own bookkeeping, and the room names. The link
+     * table is not counted.
+     */
+    pub fn heap_size(&self) -> usize {
+        let entry =
+            mem::size_of::<Pos>() + mem::size_of::<PlacedRoom>() + 2 * mem::size_of::<usize>();
+        mem::size_of::<Castle>()
+            + self
+                .rooms
+                .values()
+                .map(|room| entry + room.info.name.capacity())
+                .sum::<usize>()
     }
 }
 
 impl Castle {
-    fn action_place(&self, room: Room, pos: Pos, rot: Rot) -> Result<Castle> {
+    fn action_place(&self, room: Room, pos: Pos, rot: Rot, rule: PlacementRule) -> Result<Castle> {
+        let mut castle = self.clone();
+        castle.place_room(room, pos, rot, rule)?;
+        Ok(castle)
+    }
+    fn action_move(&self, from: Pos, to: Pos, rot: Rot, rule: PlacementRule) -> Result<Castle> {
+        let mut castle = self.clone();
+        castle.move_room(from, to, rot, rule)?;
+        Ok(castle)
+    }
+    fn action_swap(&self, pos_1: Pos, pos_2: Pos, rule: PlacementRule) -> Result<Castle> {
+        let mut castle = self.clone();
+        castle.swap_rooms(pos_1, pos_2, rule)?;
+        Ok(castle)
+    }
+    /*
+     * Place, Move and Swap done in place, for apply_with and apply_snapshotting
+     * alike. Each returns what the positions it changed held before, in the
+     * order they were changed, and leaves the castle as it was on failure.
+     */
+    pub(crate) fn place_room(
+        &mut self,
+        room: Room,
+        pos: Pos,
+        rot: Rot,
+        rule: PlacementRule,
+    ) -> Result<Vec<(Pos, Option<PlacedRoom>)>> {
         if self.damage > 0 {
             return Err(CastleError::MustDiscard);
         }
         if self.rooms.contains_key(&pos) {
             return Err(CastleError::TakenPosition);
         }
-        if !self.can_place_room(&PlacedRoom::from(room.clone(), rot), pos) {
+        let room = PlacedRoom::from(room, rot);
+        // The first room of an empty castle has nothing to connect to, so it can go anywhere.
+        if !self.rooms.is_empty() && !self.can_place_room_with(&room, pos, rule) {
             return Err(CastleError::InvalidConnection);
         }
-        let mut castle = self.clone();
-        castle.rooms.insert(pos, PlacedRoom::from(room, rot));
-        Ok(castle)
+        self.rooms.insert(pos, room);
+        Ok(vec![(pos, None)])
     }
-    fn action_move(&self, from: Pos, to: Pos, rot: Rot) -> Result<Castle> {
+    pub(crate) fn move_room(
+        &mut self,
+        from: Pos,
+        to: Pos,
+        rot: Rot,
+        rule: PlacementRule,
+    ) -> Result<Vec<(Pos, Option<PlacedRoom>)>> {
         if self.damage > 0 {
             return Err(CastleError::MustDiscard);
         }
         if from == to {
-            Err(CastleError::InvalidPosition)
-        } else if self.rooms.contains_key(&from) {
-            if !self.room_is_outer(from).unwrap() {
-                return Err(CastleError::NotOuterRoom);
-            }
-            if self.rooms.contains_key(&to) {
-                return Err(CastleError::TakenPosition);
-            }
-            let mut castle = self.clone();
-            let room = castle.rooms.remove(&from).unwrap();
-            if !castle.can_place_room(&room.rotate(rot), to) {
-                return Err(CastleError::InvalidConnection);
-            }
-            castle.rooms.insert(to, room);
-            Ok(castle)
-        } else {
-            Err(CastleError::EmptyPosition)
+            return Err(CastleError::InvalidPosition);
+        }
+        if !self.rooms.contains_key(&from) {
+            return Err(CastleError::EmptyPosition);
+        }
+        if !self.room_is_outer(from)? {
+            return Err(CastleError::NotOuterRoom);
+        }
+        if self.rooms.contains_key(&to) {
+            return Err(CastleError::TakenPosition);
+        }
+        let room = self.rooms.remove(&from).unwrap();
+        let moved = room.rotate(rot);
+        if !self.can_place_room_with(&moved, to, rule) {
+            self.rooms.insert(from, room);
+            return Err(CastleError::InvalidConnection);
         }
+        self.rooms.insert(to, moved);
+        Ok(vec![(from, Some(room)), (to, None)])
     }
-    fn action_swap(&self, pos_1: Pos, pos_2: Pos) -> Result<Castle> {
+    pub(crate) fn swap_rooms(
+        &mut self,
+        pos_1: Pos,
+        pos_2: Pos,
+        rule: PlacementRule,
+    ) -> Result<Vec<(Pos, Option<PlacedRoom>)>> {
         if self.damage > 0 {
             return Err(CastleError::MustDiscard);
         }
         if pos_1 == pos_2 {
-            Err(CastleError::InvalidPosition)
-        } else if self.rooms.contains_key(&pos_1) && self.rooms.contains_key(&pos_2) {
-            let mut castle = self.clone();
-            let room1 = castle.rooms.remove(&pos_1).unwrap();
-            let room2 = castle.rooms.remove(&pos_2).unwrap();
+            return Err(CastleError::InvalidPosition);
+        }
+        if !self.rooms.contains_key(&pos_1) || !self.rooms.contains_key(&pos_2) {
+            return Err(CastleError::EmptyPosition);
+        }
+        let room1 = self.rooms.remove(&pos_1).unwrap();
+        let room2 = self.rooms.remove(&pos_2).unwrap();
 
-            // Then, first placing room2 in pos_1 then trying to place room1 in pos_2.
-            castle.rooms.insert(pos_1, room2);
-            if !castle.can_place_room(&room1, pos_2) {
-                return Err(CastleError::InvalidConnection);
-            }
-            let room2 = castle.rooms.remove(&pos_1).unwrap();
+        // First placing room2 in pos_1 then trying to place room1 in pos_2.
+        self.rooms.insert(pos_1, room2.clone());
+        let fits_1 = self.can_place_room_with(&room1, pos_2, rule);
+        self.rooms.remove(&pos_1);
 
-            // First placing room1 in pos_2 then trying to place room2 in pos_1.
-            castle.rooms.insert(pos_2, room1);
-            if !castle.can_place_room(&room2, pos_1) {
-                return Err(CastleError::InvalidConnection);
-            }
-            castle.rooms.insert(pos_1, room2); // We passed both checks, so we can swap them.
-            Ok(castle)
-        } else {
-            Err(CastleError::EmptyPosition)
+        // Then placing room1 in pos_2 and trying to place room2 in pos_1.
+        self.rooms.insert(pos_2, room1.clone());
+        let fits_2 = self.can_place_room_with(&room2, pos_1, rule);
+        if !fits_1 || !fits_2 {
+            self.rooms.insert(pos_1, room1);
+            self.rooms.insert(pos_2, room2);
+            return Err(CastleError::InvalidConnection);
         }
+        self.rooms.insert(pos_1, room2.clone());
+        Ok(vec![(pos_1, Some(room1)), (pos_2, Some(room2))])
     }
     fn action_discard_one(&self, pos: Pos) -> Result<Castle> {
         if !self.rooms.contains_key(&pos) {
@@ -183,7 +496,7 @@ impl Castle {
             .keys()
             .filter(|p| !self.rooms[p].info.throne && self.room_is_outer(**p).unwrap())
             .collect();
-        if outer_pos.len() > 0 {
+        if !outer_pos.is_empty() {
             if self.room_is_outer(pos).unwrap() {
                 let mut castle = self.clone();
                 castle.rooms.remove(&pos).unwrap();
@@ -198,7 +511,7 @@ impl Castle {
             .keys()
             .filter(|p| !self.rooms[p].info.throne && self.room_num_connected(**p).unwrap() <= 2)
             .collect();
-        if nearly_outer_pos.len() > 0 {
+        if !nearly_outer_pos.is_empty() {
             if self.room_num_connected(pos).unwrap() <= 2 {
                 let mut castle = self.clone();
                 castle.rooms.remove(&pos).unwrap();
@@ -208,7 +521,7 @@ impl Castle {
                 return Err(CastleError::NotNearlyOuterRoom);
             }
         }
-        return Err(CastleError::MustDiscard);
+        Err(CastleError::MustDiscard)
     }
     fn action_discard(&self, poses: Vec<Pos>) -> Result<Castle> {
         if self.damage == 0 {
@@ -218,64 +531,200 @@ impl Castle {
         for pos in poses {
             castle = castle.action_discard_one(pos)?;
         }
-        if self.damage > 0 {
+        if castle.damage > 0 {
             Err(CastleError::MustDiscard)
         } else {
             Ok(castle)
         }
     }
-    pub fn action_damage(&self, diamond_damage: u8, cross_damage: u8, moon_damage: u8) -> Castle {
-        let (diamond_link, cross_link, moon_link, wild_link) = self.get_links();
-        let mut castle = self.clone();
-        if diamond_damage > diamond_link {
-            castle.damage += diamond_damage - diamond_link;
-        }
-        if cross_damage > cross_link {
-            castle.damage += cross_damage - cross_link;
-        }
-        if moon_damage > moon_link {
-            castle.damage += moon_damage - moon_link;
-        }
-        if castle.damage > wild_link {
-            castle.damage -= wild_link;
-        }
-        if castle.damage as usize >= castle.rooms.len() {
-            castle.damage -= castle.rooms.len() as u8;
-            castle.rooms = BTreeMap::new();
-        }
-        castle
+    pub fn action_damage(
+        &self,
+        diamond_damage: u8,
+        cross_damage: u8,
+        moon_damage: u8,
+    ) -> Result<Castle> {
+        Ok(self
+            .action_damage_detailed(diamond_damage, cross_damage, moon_damage)?
+            .0)
     }
     pub fn apply(&self, action: Action) -> Result<Castle> {
+        self.apply_with(action, PlacementRule::Strict)
+    }
+    pub fn apply_with(&self, action: Action, rule: PlacementRule) -> Result<Castle> {
+        self.check_phase(&action)?;
         match action {
-            Action::Place(room, pos, rot) => self.action_place(room, pos, rot),
-            Action::Move(from, to, rot) => self.action_move(from, to, rot),
-            Action::Swap(pos_1, pos_2) => self.action_swap(pos_1, pos_2),
+            Action::Place(room, pos, rot) => self.action_place(room, pos, rot, rule),
+            Action::Move(from, to, rot) => self.action_move(from, to, rot, rule),
+            Action::Swap(pos_1, pos_2) => self.action_swap(pos_1, pos_2, rule),
             Action::Discard(poses) => self.action_discard(poses),
-            Action::Damage(diamond, cross, moon) => Ok(self.action_damage(diamond, cross, moon)),
+            Action::Damage(diamond, cross, moon) => self.action_damage(diamond, cross, moon),
         }
     }
-    pub fn possible_actions(&self, shop: &Vec<Room>) -> Vec<Action> {
-        if self.damage > 0 {
-            return self
-                .all_possible_discards()
-                .into_iter()
-                .map(|poses| Action::Discard(poses))
-                .collect();
+    /*
+     * Builds the Place action for a rotated preview, or the reason it can't be applied
+     */
+    pub fn place_action(&self, room: Room, pos: Pos, rot: Rot) -> Result<Action> {
+        let action = Action::Place(room, pos, rot);
+        self.apply(action.clone())?;
+        Ok(action)
+    }
+    pub fn can_apply(&self, action: &Action) -> bool {
+        self.apply(action.clone()).is_ok()
+    }
+    /*
+     * Whether each action could be applied, in the same order as actions
+     */
+    pub fn filter_legal(&self, actions: &[Action]) -> Vec<bool> {
+        actions
+            .iter()
+            .map(|action| self.can_apply(action))
+            .collect()
+    }
+    /*
+     * Like apply, but a Place is rejected unless the room works the same as
+     * one of the shop's cards. Other actions do not involve the shop.
+     */
+    pub fn apply_from_shop(&self, action: &Action, shop: &[Room]) -> Result<Castle> {
+        if let Action::Place(room, _, _) = action {
+            if !shop.iter().any(|card| card.same_function(room)) {
+                return Err(CastleError::RoomNotInShop);
+            }
         }
-        self.all_possible_placements(shop)
+        self.apply(action.clone())
+    }
+    /*
+     * Places the shop card at index, taking it out of the shop. On failure
+     * neither the castle nor the shop is changed.
+     */
+    pub fn place_from_shop(
+        &mut self,
+        shop: &mut Vec<Room>,
+        index: usize,
+        pos: Pos,
+        rot: Rot,
+    ) -> Result<Room> {
+        let room = shop
+            .get(index)
+            .ok_or(CastleError::InvalidShopIndex(index))?;
+        *self = self.apply(Action::Place(room.clone(), pos, rot))?;
+        Ok(shop.remove(index))
+    }
+    /*
+     * Puts new_room at pos in place of the room there, keeping its rotation
+     * and reflection.
+     * The old room is gone, and new_room must still fit every neighbor.
+     */
+    pub fn replace_room(&self, pos: Pos, new_room: Room) -> Result<Castle> {
+        let mut castle = self.clone();
+        let old = castle
+            .rooms
+            .remove(&pos)
+            .ok_or(CastleError::EmptyPosition)?;
+        let room = PlacedRoom {
+            info: new_room,
+            ..old
+        };
+        if !castle.rooms.is_empty() && !castle.can_place_room(&room, pos) {
+            return Err(CastleError::InvalidConnection);
+        }
+        castle.rooms.insert(pos, room);
+        Ok(castle)
+    }
+    fn check_phase(&self, action: &Action) -> Result<()> {
+        // Damage can arrive in either phase, everything else belongs to exactly one.
+        match (self.phase(), action) {
+            (Phase::Build, Action::Discard(_)) => Err(CastleError::WrongPhase),
+            (Phase::Resolve, Action::Place(..))
+            | (Phase::Resolve, Action::Move(..))
+            | (Phase::Resolve, Action::Swap(..)) => Err(CastleError::WrongPhase),
+            _ => Ok(()),
+        }
+    }
+    pub fn possible_actions(&self, shop: &[Room]) -> Vec<Action> {
+        self.actions_iter(shop).collect()
+    }
+    /*
+     * The same actions as possible_actions, each one worked out only when the
+     * iterator reaches it. Discards are all found on the first call to next.
+     */
+    pub fn actions_iter<'a>(&'a self, shop: &'a [Room]) -> impl Iterator<Item = Action> + 'a {
+        let building = self.damage == 0;
+        let discards = Some(())
+            .filter(move |_| !building)
             .into_iter()
-            .map(|(index, pos)| Action::Place(shop[index].clone(), pos, 0))
-            .chain(
-                self.all_possible_moves()
+            .flat_map(move |_| {
+                self.all_possible_discards()
                     .into_iter()
-                    .map(|(from, to)| Action::Move(from, to, 0)),
-            )
-            .chain(
-                self.all_possible_swaps()
+                    .map(Action::Discard)
+            });
+        let placements = shop.iter().filter(move |_| building).flat_map(move |room| {
+            self.placements_of(&PlacedRoom::from(room.clone(), 0))
+                .map(move |(pos, rot)| Action::Place(room.clone(), pos, rot))
+        });
+        let moves = self
+            .rooms
+            .keys()
+            .filter(move |_| building)
+            .flat_map(move |from| {
+                self.moves_of(*from)
+                    .map(move |(to, rot)| Action::Move(*from, to, rot))
+            });
+        let swaps = self
+            .rooms
+            .keys()
+            .filter(move |_| building)
+            .flat_map(move |pos_1| {
+                self.possible_swaps(*pos_1)
                     .into_iter()
-                    .map(|(pos_1, pos_2)| Action::Swap(pos_1, pos_2)),
-            )
-            .collect()
+                    .map(move |pos_2| Action::Swap(*pos_1, pos_2))
+            });
+        discards.chain(placements).chain(moves).chain(swaps)
+    }
+    /*
+     * The Place and Move actions of possible_actions, keyed by the position
+     * they fill
+     */
+    pub fn actions_by_target(&self, shop: &[Room]) -> BTreeMap<Pos, Vec<Action>> {
+        let mut by_target: BTreeMap<Pos, Vec<Action>> = BTreeMap::new();
+        for action in self.actions_iter(shop) {
+            let target = match action {
+                Action::Place(_, pos, _) | Action::Move(_, pos, _) => pos,
+                _ => continue,
+            };
+            by_target.entry(target).or_default().push(action);
+        }
+        by_target
+    }
+    /*
+     * The Place actions possible_actions offers for the shop card at index
+     */
+    pub fn actions_for_card(&self, shop: &[Room], index: usize) -> Result<Vec<Action>> {
+        let room = shop
+            .get(index)
+            .ok_or(CastleError::InvalidShopIndex(index))?;
+        if self.damage > 0 {
+            return Ok(Vec::new());
+        }
+        Ok(self
+            .placements_of(&PlacedRoom::from(room.clone(), 0))
+            .map(|(pos, rot)| Action::Place(room.clone(), pos, rot))
+            .collect())
+    }
+    /*
+     * How many actions of each kind possible_actions would return. Kinds with
+     * no possible action are left out.
+     */
+    pub fn action_counts(&self, shop: &[Room]) -> BTreeMap<ActionKind, usize> {
+        let counts = if self.damage > 0 {
+            vec![(ActionKind::Discard, self.all_possible_discards().len())]
+        } else {
+            vec![
+                (ActionKind::Place, self.all_possible_placements(shop).len()),
+                (ActionKind::Move, self.all_possible_moves().len()),
+                (ActionKind::Swap, self.all_possible_swaps().len()),
+            ]
+        };
+        counts.into_iter().filter(|(_, count)| *count > 0).collect()
     }
     pub fn clear_rooms(&self) -> Castle {
         let mut castle = self.clone();
@@ -286,27 +735,107 @@ impl Castle {
 }
 
 impl Castle {
-    pub fn all_possible_placements(&self, shop: &Vec<Room>) -> Vec<(usize, Pos)> {
-        let mut possible = Vec::new();
-        for (i, room) in shop.iter().enumerate() {
-            for pos in self.possible_placements(&PlacedRoom::from(room.clone(), 0)) {
-                possible.push((i, pos));
-            }
+    pub fn all_possible_placements(&self, shop: &[Room]) -> Vec<(usize, Pos, Rot)> {
+        shop.iter()
+            .enumerate()
+            .flat_map(|(i, room)| {
+                self.placements_of(&PlacedRoom::from(room.clone(), 0))
+                    .map(move |(pos, rot)| (i, pos, rot))
+            })
+            .collect()
+    }
+    /*
+     * all_possible_placements over several shops, as (shop, card, pos, rotation)
+     */
+    pub fn all_possible_placements_multi(
+        &self,
+        shops: &[&[Room]],
+    ) -> Vec<(usize, usize, Pos, Rot)> {
+        shops
+            .iter()
+            .enumerate()
+            .flat_map(|(shop_index, shop)| {
+                self.all_possible_placements(shop)
+                    .into_iter()
+                    .map(move |(card, pos, rot)| (shop_index, card, pos, rot))
+            })
+            .collect()
+    }
+    pub fn all_possible_moves(&self) -> Vec<(Pos, Pos, Rot)> {
+        self.rooms
+            .keys()
+            .flat_map(|from| self.moves_of(*from).map(move |(to, rot)| (*from, to, rot)))
+            .collect()
+    }
+    /*
+     * Every (pos, rotation) room can be placed at, trying each of its distinct
+     * rotations in turn and positions in order. The rotation room already has
+     * is ignored; a reflection is kept.
+     */
+    pub fn placements_of<'a>(&'a self, room: &PlacedRoom) -> impl Iterator<Item = (Pos, Rot)> + 'a {
+        let room = room.clone();
+        room.info
+            .distinct_rotations()
+            .into_iter()
+            .flat_map(move |rot| {
+                self.possible_placements_sorted(&room.rotate(rot))
+                    .into_iter()
+                    .map(move |pos| (pos, rot))
+            })
+    }
+    /*
+     * placements_of for the room at from in the castle without it, leaving out
+     * from itself. Empty unless the room is outer.
+     */
+    fn moves_of(&self, from: Pos) -> impl Iterator<Item = (Pos, Rot)> {
+        let mut moves = Vec::new();
+        if let Ok(true) = self.room_is_outer(from) {
+            let mut castle = self.clone();
+            let room = castle.rooms.remove(&from).unwrap();
+            moves = castle
+                .placements_of(&room)
+                .filter(|(to, _)| *to != from)
+                .collect();
         }
-        possible
+        moves.into_iter()
     }
-    pub fn all_possible_moves(&self) -> Vec<(Pos, Pos)> {
-        let mut possible = Vec::new();
+    /*
+     * Number of moves all_possible_moves would return, counted without
+     * collecting them: every empty cell in the bounding box grown by one is
+     * tried in each distinct rotation.
+     */
+    pub fn count_moves(&self) -> usize {
+        let ((min_x, min_y), (max_x, max_y)) = match self.bounding_box() {
+            Some(bounds) => bounds,
+            None => return 0,
+        };
+        let mut castle = self.clone();
+        let mut count = 0;
         for from in self.rooms.keys() {
-            possible.append(
-                &mut self
-                    .possible_moves(*from, 0)
-                    .into_iter()
-                    .map(|to| (*from, to))
-                    .collect(),
-            );
+            if !self.room_is_outer(*from).unwrap() {
+                continue;
+            }
+            let room = castle.rooms.remove(from).unwrap();
+            for rot in room.info.distinct_rotations() {
+                let room = room.rotate(rot);
+                for x in min_x as i16 - 1..=max_x as i16 + 1 {
+                    for y in min_y as i16 - 1..=max_y as i16 + 1 {
+                        let to = match (i8::try_from(x), i8::try_from(y)) {
+                            (Ok(x), Ok(y)) => (x, y),
+                            _ => continue,
+                        };
+                        if to != *from
+                            && !castle.rooms.contains_key(&to)
+                            && castle.can_place_room(&room, to)
+                        {
+                            count += 1;
+                        }
+                    }
+                }
+            }
+            castle.rooms.insert(*from, room);
         }
-        possible
+        count
     }
     pub fn all_possible_swaps(&self) -> Vec<(Pos, Pos)> {
         // Since the number of rooms is limited, we can just brute force and check all possible swaps
@@ -340,7 +869,11 @@ impl Castle {
                     &mut castle
                         .possible_discard()
                         .into_iter()
-                        .map(|pos| (castle.action_discard_one(pos).unwrap(), vec![pos]))
+                        .map(|pos| {
+                            let mut discards = discards.clone();
+                            discards.push(pos);
+                            (castle.action_discard_one(pos).unwrap(), discards)
+                        })
                         .collect(),
                 );
             }
@@ -361,7 +894,7 @@ impl Castle {
                 possible.push(*pos);
             }
         }
-        if possible.len() > 0 {
+        if !possible.is_empty() {
             possible
         } else {
             for (pos, room) in self.rooms.iter() {
@@ -383,13 +916,21 @@ impl Castle {
         }
         placable.into_iter().collect()
     }
+    /*
+     * possible_placements in position order
+     */
+    pub fn possible_placements_sorted(&self, room: &PlacedRoom) -> Vec<Pos> {
+        let mut placable = self.possible_placements(room);
+        placable.sort_unstable();
+        placable
+    }
     pub fn possible_moves(&self, from: Pos, rotation: u16) -> Vec<Pos> {
         let mut castle = self.clone();
         let mut possible = Vec::new();
         if let Ok(room_is_outer) = self.room_is_outer(from) {
             if room_is_outer {
                 let room = castle.rooms.remove(&from).unwrap();
-                for to in castle.possible_placements(&room.rotate(rotation)) {
+                for to in castle.possible_placements_sorted(&room.rotate(rotation)) {
                     if from != to {
                         possible.push(to);
                     }
@@ -399,15 +940,61 @@ impl Castle {
         }
         possible
     }
+    pub fn legal_placement_rotations(&self, room: &Room, pos: Pos) -> Vec<Rot> {
+        if self.rooms.contains_key(&pos) {
+            return Vec::new();
+        }
+        [0, 90, 180, 270]
+            .iter()
+            .copied()
+            .filter(|rot| self.can_place_room(&PlacedRoom::from(room.clone(), *rot), pos))
+            .collect()
+    }
+    /*
+     * Sides of the moved room that would face a neighbor without connecting.
+     * An empty result means the move is legal: a destination with no
+     * connecting neighbor at all is reported as InvalidConnection instead.
+     */
+    pub fn move_mismatches(&self, from: Pos, to: Pos, rot: Rot) -> Result<Vec<Direction>> {
+        if from == to {
+            return Err(CastleError::InvalidPosition);
+        }
+        if !self.room_is_outer(from)? {
+            return Err(CastleError::NotOuterRoom);
+        }
+        if self.rooms.contains_key(&to) {
+            return Err(CastleError::TakenPosition);
+        }
+        let mut castle = self.clone();
+        let room = castle.rooms.remove(&from).unwrap().rotate(rot);
+        let connections = room.get_connections();
+        let mut mismatches = Vec::new();
+        let mut count = 0;
+        for dir in Direction::ALL {
+            if let Some(con_room) = castle.rooms.get(&connecting(to)[dir.index()]) {
+                match connections[dir.index()]
+                    .connect(&con_room.get_connections()[dir.opposite().index()])
+                {
+                    Some(true) => count += 1,
+                    Some(false) => mismatches.push(dir),
+                    None => (),
+                }
+            }
+        }
+        if mismatches.is_empty() && count == 0 {
+            return Err(CastleError::InvalidConnection);
+        }
+        Ok(mismatches)
+    }
     pub fn possible_swaps(&self, from: Pos) -> Vec<Pos> {
         // Since the number of rooms is limited, we can just brute force and check all possible swaps
         let mut possible = Vec::new();
-        let pos_1 = &from;
-        if let Some(room1) = self.rooms.get(&from) {
-            for (pos_2, room2) in self.rooms.iter() {
-                if pos_1 != pos_2
-                    && self.can_place_room(room1, *pos_2)
-                    && self.can_place_room(room2, *pos_1)
+        if self.rooms.contains_key(&from) {
+            for pos_2 in self.rooms.keys() {
+                if from != *pos_2
+                    && self
+                        .action_swap(from, *pos_2, PlacementRule::Strict)
+                        .is_ok()
                 {
                     possible.push(*pos_2);
                 }
@@ -422,23 +1009,52 @@ impl Castle {
      * Does not check for already existing room at position
      */
     fn can_place_room(&self, room: &PlacedRoom, pos: Pos) -> bool {
+        self.can_place_room_with(room, pos, PlacementRule::Strict)
+    }
+    /*
+     * Does not check for already existing room at position
+     */
+    pub fn can_place_room_lenient(&self, room: &PlacedRoom, pos: Pos) -> bool {
+        self.can_place_room_with(room, pos, PlacementRule::Lenient)
+    }
+    fn can_place_room_with(&self, room: &PlacedRoom, pos: Pos, rule: PlacementRule) -> bool {
+        metrics::count_can_place_room();
         let mut count = 0;
         let mut connect = true;
         for (i, con_pos) in connecting(pos).iter().enumerate() {
-            if let Some(con_room) = self.rooms.get(&con_pos) {
+            if let Some(con_room) = self.rooms.get(con_pos) {
                 if let Some(is_connected) =
                     room.get_connections()[i].connect(&con_room.get_connections()[(i + 2) % 4])
                 {
                     if is_connected {
                         count += 1;
-                    } else {
+                    } else if rule == PlacementRule::Strict {
                         connect = false;
                         break;
                     }
                 }
             }
         }
-        return connect && count > 0;
+        connect && count > 0
+    }
+    /*
+     * Flood fill over connected rooms, starting from (and including) start
+     */
+    fn reachable_from(&self, start: Pos) -> HashSet<Pos> {
+        let mut reached = HashSet::new();
+        if !self.rooms.contains_key(&start) {
+            return reached;
+        }
+        let mut queue = vec![start];
+        reached.insert(start);
+        while let Some(pos) = queue.pop() {
+            for con_pos in self.connected_neighbors_iter(pos) {
+                if reached.insert(con_pos) {
+                    queue.push(con_pos);
+                }
+            }
+        }
+        reached
     }
     fn room_is_outer(&self, pos: Pos) -> Result<bool> {
         Ok(self.room_num_connected(pos)? == 1)
@@ -447,7 +1063,7 @@ impl Castle {
         if let Some(room) = self.rooms.get(&pos) {
             let mut count = 0;
             for (i, con_pos) in connecting(pos).iter().enumerate() {
-                if let Some(con_room) = self.rooms.get(&con_pos) {
+                if let Some(con_room) = self.rooms.get(con_pos) {
                     if let Some(is_connected) =
                         room.get_connections()[i].connect(&con_room.get_connections()[(i + 2) % 4])
                     {
@@ -467,9 +1083,9 @@ impl Castle {
             let connections = room.get_connections();
             for (i, con_pos) in connecting(pos).iter().enumerate() {
                 if connections[i].power() {
-                    if let Some(con_room) = self.rooms.get(&con_pos) {
+                    if let Some(con_room) = self.rooms.get(con_pos) {
                         if let Ok(link) =
-                            connections[i].link(&con_room.get_connections()[(i + 2) % 4])
+                            self.link(&connections[i], &con_room.get_connections()[(i + 2) % 4])
                         {
                             if link.power() {
                                 continue;
@@ -486,15 +1102,87 @@ impl Castle {
     }
 }
 
+fn is_zero(damage: &u8) -> bool {
+    *damage == 0
+}
+
+fn is_false(flag: &bool) -> bool {
+    !*flag
+}
+
+/*
+ * Neighbors in N, E, S, W order. The grid wraps around at the edges of i8.
+ */
 fn connecting(pos: Pos) -> [Pos; 4] {
     let (x, y) = pos;
-    [(x, y - 1), (x + 1, y), (x, y + 1), (x - 1, y)]
+    [
+        (x, y.wrapping_sub(1)),
+        (x.wrapping_add(1), y),
+        (x, y.wrapping_add(1)),
+        (x.wrapping_sub(1), y),
+    ]
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ron;
+
+    pub(crate) fn throne() -> Room {
+        Room {
+            name: "Throne Room (White)".to_string(),
+            throne: true,
+            treasure: 0,
+            connections: [Connection::Wild; 4],
+        }
+    }
+
+    pub(crate) fn room(name: &str, treasure: u8, connections: [Connection; 4]) -> Room {
+        Room {
+            name: name.to_string(),
+            throne: false,
+            treasure,
+            connections,
+        }
+    }
+
+    /*
+     * A link table where no two sides can face each other
+     */
+    pub(crate) fn empty_link_table() -> LinkTable {
+        ron::from_str("(links:[])").unwrap()
+    }
+
+    /*
+     * One treasure, linking only to the west
+     */
+    pub(crate) fn small_vault() -> Room {
+        room(
+            "Small Vault",
+            1,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        )
+    }
+
+    /*
+     * No treasure, linking east and west
+     */
+    pub(crate) fn hall() -> Room {
+        room(
+            "Hall",
+            0,
+            [
+                Connection::None,
+                Connection::Cross(false),
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        )
+    }
 
     #[test]
     fn test_new() {
@@ -504,7 +1192,7 @@ mod tests {
                 name: \"Throne Room (White)\",
                 treasure: 0,
                 rotation: 0,
-                connections: (Wild, Wild, Wild, Wild)
+                connections: (\"wild\", \"wild\", \"wild\", \"wild\")
             )",
         )
         .unwrap();
@@ -519,7 +1207,7 @@ mod tests {
                 name: \"Throne Room (White)\",
                 treasure: 0,
                 rotation: 0,
-                connections: (Wild, Wild, Wild, Wild)
+                connections: (\"wild\", \"wild\", \"wild\", \"wild\")
             )",
         )
         .unwrap();
@@ -531,35 +1219,35 @@ mod tests {
                 treasure: 1,
                 name: \"Small Vault\",
                 rotation: 0,
-                connections: (None, None, None, Cross(false))
+                connections: (\"none\", \"none\", \"none\", \"cross\")
             ),
             Room(
                 throne: false,
                 treasure: 1,
                 name: \"Small Vault\",
                 rotation: 0,
-                connections: (None, Diamond(false), None, None)
+                connections: (\"none\", \"diamond\", \"none\", \"none\")
             ),
             Room(
                 throne: false,
                 treasure: 1,
                 name: \"Small Vault\",
                 rotation: 0,
-                connections: (None, None, Moon(false), None)
+                connections: (\"none\", \"none\", \"moon\", \"none\")
             ),
             Room(
                 throne: false,
                 treasure: 1,
                 name: \"Small Vault\",
                 rotation: 0,
-                connections: (Cross(false), None, None, None)
+                connections: (\"cross\", \"none\", \"none\", \"none\")
             ),
         ]",
         )
         .unwrap();
         let shop: Vec<Room> = shop.into_iter().collect();
         let actions = castle.possible_actions(&shop);
-        assert_eq!(actions.len(), 4);
+        assert_eq!(actions.len(), 16);
     }
 
     #[test]
@@ -570,7 +1258,7 @@ mod tests {
                 name: \"Throne Room (White)\",
                 treasure: 0,
                 rotation: 0,
-                connections: (Wild, Wild, Wild, Wild)
+                connections: (\"wild\", \"wild\", \"wild\", \"wild\")
             )",
         )
         .unwrap();
@@ -582,28 +1270,28 @@ mod tests {
                 treasure: 1,
                 name: \"Small Vault\",
                 rotation: 0,
-                connections: (None, None, None, Cross(false))
+                connections: (\"none\", \"none\", \"none\", \"cross\")
             ),
             Room(
                 throne: false,
                 treasure: 1,
                 name: \"Small Vault\",
                 rotation: 0,
-                connections: (None, Diamond(false), None, None)
+                connections: (\"none\", \"diamond\", \"none\", \"none\")
             ),
             Room(
                 throne: false,
                 treasure: 1,
                 name: \"Small Vault\",
                 rotation: 0,
-                connections: (None, None, Moon(false), None)
+                connections: (\"none\", \"none\", \"moon\", \"none\")
             ),
             Room(
                 throne: false,
                 treasure: 1,
                 name: \"Small Vault\",
                 rotation: 0,
-                connections: (Cross(false), None, None, None)
+                connections: (\"cross\", \"none\", \"none\", \"none\")
             ),
         ]",
         )
@@ -616,4 +1304,764 @@ mod tests {
         let new_castle = result.unwrap();
         assert_eq!(new_castle.rooms.len(), 2);
     }
+
+    #[test]
+    fn test_phase() {
+        let mut castle = Castle::new(throne());
+        assert_eq!(castle.phase(), Phase::Build);
+        castle.damage = 1;
+        assert_eq!(castle.phase(), Phase::Resolve);
+    }
+
+    #[test]
+    fn test_wrong_phase() {
+        let vault = room(
+            "Small Vault",
+            1,
+            [
+                Connection::Cross(false),
+                Connection::None,
+                Connection::None,
+                Connection::None,
+            ],
+        );
+        let mut castle = Castle::new(throne());
+        castle.damage = 1;
+        assert!(matches!(
+            castle.apply(Action::Place(vault, (0, 1), 0)),
+            Err(CastleError::WrongPhase)
+        ));
+        assert!(matches!(
+            castle.apply(Action::Swap((0, 0), (0, 1))),
+            Err(CastleError::WrongPhase)
+        ));
+
+        let castle = Castle::new(throne());
+        assert!(matches!(
+            castle.apply(Action::Discard(vec![(0, 0)])),
+            Err(CastleError::WrongPhase)
+        ));
+    }
+
+    #[test]
+    fn test_is_stable() {
+        let vault = small_vault();
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault.clone(), (1, 0), 0))
+            .unwrap();
+        assert!(castle.is_stable());
+
+        let mut damaged = castle.clone();
+        damaged.damage = 1;
+        assert!(!damaged.is_stable());
+
+        // The throne's wild side now faces a wall of the vault.
+        let mut broken = castle.clone();
+        broken.rooms.insert((0, 1), PlacedRoom::from(vault, 0));
+        assert!(matches!(
+            broken.validate(),
+            Err(CastleError::InvalidConnection)
+        ));
+        assert!(!broken.is_stable());
+    }
+
+    #[test]
+    fn test_lenient_placement() {
+        let corridor = room(
+            "Corridor",
+            0,
+            [
+                Connection::None,
+                Connection::Cross(false),
+                Connection::Cross(false),
+                Connection::None,
+            ],
+        );
+        let vault = small_vault();
+        let castle = Castle::new(throne())
+            .apply(Action::Place(corridor, (0, -1), 0))
+            .unwrap()
+            .apply(Action::Place(vault, (1, -1), 0))
+            .unwrap();
+        // Connects to the throne on the west, but faces the vault's wall on the north.
+        let hall = room(
+            "Hall",
+            0,
+            [
+                Connection::Cross(false),
+                Connection::None,
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let placed = PlacedRoom::from(hall.clone(), 0);
+        assert!(!castle.can_place_room(&placed, (1, 0)));
+        assert!(castle.can_place_room_lenient(&placed, (1, 0)));
+
+        let action = Action::Place(hall, (1, 0), 0);
+        assert!(matches!(
+            castle.apply(action.clone()),
+            Err(CastleError::InvalidConnection)
+        ));
+        let castle = castle.apply_with(action, PlacementRule::Lenient).unwrap();
+        assert_eq!(castle.rooms.len(), 4);
+    }
+
+    #[test]
+    fn test_swap_action() {
+        let hall = hall();
+        let gallery = room(
+            "Gallery",
+            0,
+            [
+                Connection::None,
+                Connection::Moon(false),
+                Connection::None,
+                Connection::Moon(false),
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(hall.clone(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(gallery.clone(), (-1, 0), 180))
+            .unwrap();
+        let swapped = castle.apply(Action::Swap((1, 0), (-1, 0))).unwrap();
+        assert_eq!(swapped.rooms.len(), 3);
+        assert_eq!(swapped.rooms[&(-1, 0)], PlacedRoom::from(hall, 0));
+        assert_eq!(swapped.rooms[&(1, 0)], PlacedRoom::from(gallery, 180));
+        assert_eq!(swapped.rooms[&(0, 0)], castle.rooms[&(0, 0)]);
+    }
+
+    #[test]
+    fn test_move_mismatches() {
+        let hall = hall();
+        let vault = small_vault();
+        let castle = Castle::new(throne())
+            .apply(Action::Place(hall, (0, -1), 90))
+            .unwrap()
+            .apply(Action::Place(vault, (1, 0), 0))
+            .unwrap();
+        // The vault's only side would face the hall's wall.
+        assert_eq!(
+            castle.move_mismatches((1, 0), (-1, -1), 180).unwrap(),
+            vec![Direction::East]
+        );
+        assert!(castle
+            .move_mismatches((1, 0), (-1, 0), 180)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_rotated_placements() {
+        let vault = small_vault();
+        let castle = Castle::new(throne());
+        let shop = [vault.clone()];
+        let mut placements = castle.all_possible_placements(&shop);
+        placements.sort();
+        assert_eq!(
+            placements,
+            vec![
+                (0, (-1, 0), 180),
+                (0, (0, -1), 270),
+                (0, (0, 1), 90),
+                (0, (1, 0), 0)
+            ]
+        );
+
+        let hall = hall();
+        assert_eq!(hall.distinct_rotations(), vec![0, 90]);
+        assert_eq!(throne().distinct_rotations(), vec![0]);
+        assert_eq!(
+            castle
+                .placements_of(&PlacedRoom::from(hall, 270))
+                .collect::<Vec<_>>(),
+            vec![((-1, 0), 0), ((1, 0), 0), ((0, -1), 90), ((0, 1), 90)]
+        );
+
+        let castle = castle.apply(Action::Place(vault, (1, 0), 0)).unwrap();
+        let moves = castle.all_possible_moves();
+        assert_eq!(moves.len(), 3);
+        assert!(moves.contains(&((1, 0), (0, 1), 90)));
+        assert!(castle
+            .possible_actions(&[])
+            .contains(&Action::Move((1, 0), (-1, 0), 180)));
+    }
+
+    #[test]
+    fn test_action_counts() {
+        let vault = small_vault();
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault.clone(), (1, 0), 0))
+            .unwrap();
+
+        let hub = room("Hub", 0, [Connection::Cross(false); 4]);
+        // The hub looks the same in every rotation, so each free cell counts once.
+        let counts = castle.action_counts(&[hub]);
+        assert_eq!(counts[&ActionKind::Place], 3);
+        assert!(!counts.contains_key(&ActionKind::Discard));
+
+        let mut damaged = castle;
+        damaged.damage = 1;
+        let counts = damaged.action_counts(&[vault]);
+        assert_eq!(
+            counts.keys().collect::<Vec<_>>(),
+            vec![&ActionKind::Discard]
+        );
+        assert!(counts[&ActionKind::Discard] > 0);
+    }
+
+    #[test]
+    fn test_is_outer() {
+        let hall = hall();
+        let castle = Castle::new(throne())
+            .apply(Action::Place(hall.clone(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(hall.clone(), (2, 0), 0))
+            .unwrap()
+            .apply(Action::Place(hall, (-1, 0), 0))
+            .unwrap();
+        assert!(castle.is_outer((2, 0)).unwrap());
+        assert!(castle.is_outer((-1, 0)).unwrap());
+        assert!(!castle.is_outer((1, 0)).unwrap());
+        assert!(!castle.is_outer((0, 0)).unwrap());
+        assert_eq!(castle.connection_degree((1, 0)).unwrap(), 2);
+        assert!(castle.is_outer((3, 0)).is_err());
+    }
+
+    #[test]
+    fn test_legal_placement_rotations() {
+        let vault = small_vault();
+        let castle = Castle::new(throne());
+        assert_eq!(castle.legal_placement_rotations(&vault, (0, 1)), vec![90]);
+        assert_eq!(castle.legal_placement_rotations(&vault, (1, 0)), vec![0]);
+        assert!(castle.legal_placement_rotations(&vault, (0, 0)).is_empty());
+        assert!(castle.legal_placement_rotations(&vault, (2, 2)).is_empty());
+    }
+
+    #[test]
+    fn test_count_moves() {
+        let hall = hall();
+        let vault = small_vault();
+        // Turning the hall by 180 degrees gives the same sides, so those
+        // moves are only counted once.
+        let with_hall = Castle::new(throne())
+            .apply(Action::Place(hall.clone(), (1, 0), 0))
+            .unwrap();
+        assert_eq!(with_hall.count_moves(), 4);
+        let castles = vec![
+            Castle::new(throne()),
+            with_hall,
+            Castle::new(throne())
+                .apply(Action::Place(vault.clone(), (1, 0), 0))
+                .unwrap(),
+            Castle::new(throne())
+                .apply(Action::Place(hall, (1, 0), 0))
+                .unwrap()
+                .apply(Action::Place(vault.clone(), (2, 0), 0))
+                .unwrap()
+                .apply(Action::Place(vault, (0, 1), 90))
+                .unwrap(),
+        ];
+        for castle in castles {
+            assert_eq!(castle.count_moves(), castle.all_possible_moves().len());
+        }
+    }
+
+    #[test]
+    fn test_get_links_malformed() {
+        let vault = small_vault();
+        let mut castle = Castle::new(throne())
+            .apply(Action::Place(vault.clone(), (1, 0), 0))
+            .unwrap();
+        assert_eq!(castle.get_links().unwrap(), (0, 1, 0, 0));
+        castle.rooms.insert((0, 1), PlacedRoom::from(vault, 0));
+        assert!(matches!(
+            castle.get_links(),
+            Err(CastleError::InvalidConnection)
+        ));
+        assert!(castle.total_links().is_err());
+        assert!(castle.apply(Action::Damage(0, 1, 0)).is_err());
+    }
+
+    #[test]
+    fn test_links_in_region() {
+        let hall = hall();
+        let gallery = room(
+            "Gallery",
+            0,
+            [
+                Connection::None,
+                Connection::Moon(false),
+                Connection::None,
+                Connection::Moon(false),
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(hall.clone(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(hall, (2, 0), 0))
+            .unwrap()
+            .apply(Action::Place(gallery.clone(), (-1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(gallery, (-2, 0), 0))
+            .unwrap();
+        let east = castle.links_in_region((1, -5), (5, 5)).unwrap();
+        let west = castle.links_in_region((-5, -5), (-1, 5)).unwrap();
+        assert_eq!(east, (0, 1, 0, 0));
+        assert_eq!(west, (0, 0, 1, 0));
+        // The two links to the throne straddle both wings.
+        assert_eq!(castle.get_links().unwrap(), (0, 2, 2, 0));
+        assert_eq!(castle.total_links().unwrap(), 4);
+        assert_eq!(
+            castle.links_in_region((-5, -5), (5, 5)).unwrap(),
+            castle.get_links().unwrap()
+        );
+        assert!(castle.links_in_region((1, 1), (0, 0)).is_err());
+    }
+
+    #[test]
+    fn test_new_with_damage() {
+        let castle = Castle::new_with_damage(throne(), 1).unwrap();
+        assert_eq!(castle.damage, 1);
+        assert_eq!(castle.phase(), Phase::Resolve);
+        assert!(castle.is_lost());
+        assert!(castle.possible_discard().is_empty());
+        assert!(!Castle::new_with_damage(throne(), 0).unwrap().is_lost());
+        assert!(matches!(
+            Castle::new_with_damage(throne(), 2),
+            Err(CastleError::ExcessDamage)
+        ));
+    }
+
+    #[test]
+    fn test_place_into_empty() {
+        let castle = Castle::new_empty()
+            .apply(Action::Place(throne(), (3, 3), 0))
+            .unwrap();
+        assert_eq!(castle.rooms.keys().collect::<Vec<_>>(), vec![&(3, 3)]);
+        assert!(castle.validate().is_ok());
+        // Only the seed room is exempt from connecting.
+        assert!(matches!(
+            castle.apply(Action::Place(throne(), (5, 5), 0)),
+            Err(CastleError::InvalidConnection)
+        ));
+    }
+
+    #[test]
+    fn test_possible_actions_apply() {
+        let hall = hall();
+        let vault = small_vault();
+        let shop = vec![hall.clone(), vault.clone()];
+        let castles = vec![
+            Castle::new(throne()),
+            Castle::new(throne())
+                .apply(Action::Place(hall, (1, 0), 0))
+                .unwrap()
+                .apply(Action::Place(vault, (0, 1), 90))
+                .unwrap(),
+        ];
+        for castle in castles {
+            let actions = castle.possible_actions(&shop);
+            assert!(actions.iter().any(|a| matches!(a, Action::Place(_, _, 90))));
+            for action in actions {
+                assert!(castle.apply(action.clone()).is_ok(), "{:?}", action);
+            }
+        }
+    }
+
+    #[test]
+    fn test_serialize_skips_zero_damage() {
+        let castle = Castle::new(throne());
+        let serialized = ron::to_string(&castle).unwrap();
+        assert!(!serialized.contains("damage"));
+        let parsed: Castle = ron::from_str(&serialized).unwrap();
+        assert_eq!(parsed.damage, 0);
+        assert_eq!(parsed, castle);
+
+        let mut damaged = castle;
+        damaged.damage = 2;
+        let serialized = ron::to_string(&damaged).unwrap();
+        assert!(serialized.contains("damage:2"));
+        assert_eq!(ron::from_str::<Castle>(&serialized).unwrap(), damaged);
+    }
+
+    #[test]
+    fn test_all_possible_discards_multi() {
+        let hall = hall();
+        let mut castle = Castle::new(throne())
+            .apply(Action::Place(hall.clone(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(hall, (-1, 0), 0))
+            .unwrap();
+        castle.damage = 2;
+        let discards = castle.all_possible_discards();
+        assert_eq!(discards.len(), 2);
+        for poses in discards {
+            assert_eq!(poses.len(), 2);
+            let discarded = poses
+                .iter()
+                .fold(castle.clone(), |c, pos| c.action_discard_one(*pos).unwrap());
+            assert_eq!(discarded.damage, 0);
+            assert_eq!(discarded.rooms.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_apply_discard() {
+        let hall = hall();
+        let mut castle = Castle::new(throne())
+            .apply(Action::Place(hall.clone(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(hall, (-1, 0), 0))
+            .unwrap();
+        castle.damage = 2;
+        let discarded = castle
+            .apply(Action::Discard(vec![(1, 0), (-1, 0)]))
+            .unwrap();
+        assert_eq!(discarded.damage, 0);
+        assert_eq!(discarded.rooms.len(), 1);
+        assert!(matches!(
+            castle.apply(Action::Discard(vec![(1, 0)])),
+            Err(CastleError::MustDiscard)
+        ));
+    }
+
+    #[test]
+    fn test_actions_iter() {
+        let hall = hall();
+        let shop = vec![hall.clone(), throne()];
+        let mut castle = Castle::new(throne())
+            .apply(Action::Place(hall.clone(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(hall, (-1, 0), 0))
+            .unwrap();
+        let mut iterated: Vec<Action> = castle.actions_iter(&shop).collect();
+        let mut collected = castle.possible_actions(&shop);
+        assert!(!collected.is_empty());
+        iterated.sort();
+        collected.sort();
+        assert_eq!(iterated, collected);
+
+        castle.damage = 1;
+        let iterated: Vec<Action> = castle.actions_iter(&shop).collect();
+        assert!(iterated.iter().all(|a| matches!(a, Action::Discard(_))));
+        assert_eq!(iterated.len(), castle.all_possible_discards().len());
+    }
+
+    #[test]
+    fn test_apply_from_shop() {
+        let hall = hall();
+        let vault = small_vault();
+        let castle = Castle::new(throne());
+        let shop = vec![hall.clone()];
+        assert!(matches!(
+            castle.apply_from_shop(&Action::Place(vault, (1, 0), 0), &shop),
+            Err(CastleError::RoomNotInShop)
+        ));
+        let castle = castle
+            .apply_from_shop(&Action::Place(hall, (1, 0), 0), &shop)
+            .unwrap();
+        assert!(castle
+            .apply_from_shop(&Action::Move((1, 0), (0, 1), 90), &[])
+            .is_ok());
+    }
+
+    #[test]
+    fn test_place_from_shop() {
+        let vault = small_vault();
+        let mut castle = Castle::new(throne());
+        let mut shop = vec![throne(), vault.clone()];
+        let (castle_before, shop_before) = (castle.clone(), shop.clone());
+        assert!(matches!(
+            castle.place_from_shop(&mut shop, 1, (1, 0), 90),
+            Err(CastleError::InvalidConnection)
+        ));
+        assert!(matches!(
+            castle.place_from_shop(&mut shop, 2, (1, 0), 0),
+            Err(CastleError::InvalidShopIndex(2))
+        ));
+        assert_eq!(castle, castle_before);
+        assert_eq!(shop, shop_before);
+
+        assert_eq!(
+            castle.place_from_shop(&mut shop, 1, (1, 0), 0).unwrap(),
+            vault
+        );
+        assert_eq!(shop, vec![throne()]);
+        assert_eq!(castle.rooms[&(1, 0)].info, vault);
+    }
+
+    #[test]
+    fn test_filter_legal() {
+        let vault = small_vault();
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault.clone(), (1, 0), 0))
+            .unwrap();
+        let actions = [
+            Action::Place(vault.clone(), (0, 1), 90),
+            Action::Place(vault.clone(), (0, 1), 0),
+            Action::Place(vault, (1, 0), 0),
+            Action::Move((1, 0), (-1, 0), 180),
+            Action::Swap((0, 0), (0, 0)),
+            Action::Discard(vec![(1, 0)]),
+        ];
+        let mask = castle.filter_legal(&actions);
+        assert_eq!(mask, vec![true, false, false, true, false, false]);
+        for (action, legal) in actions.iter().zip(mask) {
+            assert_eq!(castle.can_apply(action), legal);
+        }
+    }
+
+    #[test]
+    fn test_reflect() {
+        let window = room(
+            "Window",
+            0,
+            [
+                Connection::None,
+                Connection::Diamond(false),
+                Connection::None,
+                Connection::None,
+            ],
+        );
+        let reflected = PlacedRoom::from(window.clone(), 0).reflect();
+        assert_eq!(
+            reflected.get_connections(),
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Diamond(false)
+            ]
+        );
+        assert!(!reflected.reflect().reflected);
+        for rot in [0, 90, 180, 270] {
+            assert_eq!(
+                reflected.rotate(rot).get_connections(),
+                window.mirror_horizontal().get_rotated_connections(rot)
+            );
+        }
+
+        let castle = Castle::new(throne());
+        assert_eq!(
+            castle.possible_placements_sorted(&PlacedRoom::from(window, 0)),
+            vec![(-1, 0)]
+        );
+        assert_eq!(castle.possible_placements_sorted(&reflected), vec![(1, 0)]);
+
+        let mut reflected_castle = castle.clone();
+        reflected_castle.rooms.insert((1, 0), reflected);
+        assert!(reflected_castle.validate().is_ok());
+        assert_eq!(reflected_castle.get_links().unwrap(), (1, 0, 0, 0));
+        let serialized = ron::to_string(&reflected_castle).unwrap();
+        assert!(serialized.contains("reflected:true"));
+        assert_eq!(
+            ron::from_str::<Castle>(&serialized).unwrap(),
+            reflected_castle
+        );
+        assert!(!ron::to_string(&castle).unwrap().contains("reflected"));
+        let moved = reflected_castle
+            .apply(Action::Move((1, 0), (-1, 0), 180))
+            .unwrap();
+        assert!(moved.rooms[&(-1, 0)].reflected);
+    }
+
+    #[test]
+    fn test_try_rotate() {
+        let room = PlacedRoom::from(throne(), 0);
+        assert!(matches!(
+            room.try_rotate(45),
+            Err(RoomError::InvalidRotation(45))
+        ));
+        assert_eq!(room.try_rotate(180).unwrap().rotation, 180);
+        assert!(room.try_rotate(360).is_err());
+    }
+
+    #[test]
+    fn test_placement_order_is_deterministic() {
+        let hall = hall();
+        let shop = vec![hall.clone(), throne()];
+        let castle = Castle::new(throne())
+            .apply(Action::Place(hall, (1, 0), 0))
+            .unwrap();
+        let placements = castle.possible_placements_sorted(&PlacedRoom::from(throne(), 0));
+        let mut sorted = placements.clone();
+        sorted.sort();
+        assert_eq!(placements, sorted);
+        let first = castle.possible_actions(&shop);
+        for _ in 0..10 {
+            assert_eq!(castle.possible_actions(&shop), first);
+        }
+    }
+
+    #[test]
+    fn test_heap_size() {
+        let hall = hall();
+        let small = Castle::new(throne());
+        let mut large = small.clone();
+        for x in 1..10 {
+            large = large.apply(Action::Place(hall.clone(), (x, 0), 0)).unwrap();
+        }
+        assert_eq!(large.rooms.len(), 10);
+        assert!(large.heap_size() > small.heap_size());
+        let mut renamed = small.clone();
+        renamed
+            .rooms
+            .get_mut(&(0, 0))
+            .unwrap()
+            .info
+            .name
+            .push_str(" of the Realm");
+        assert!(renamed.heap_size() > small.heap_size());
+    }
+
+    #[test]
+    fn test_all_possible_placements_multi() {
+        let vault = small_vault();
+        let hall = hall();
+        let castle = Castle::new(throne());
+        let colored = [vault.clone()];
+        let neutral = [hall.clone(), vault];
+        let placements = castle.all_possible_placements_multi(&[&colored, &neutral]);
+        assert_eq!(placements.len(), 4 + 4 + 4);
+        assert!(placements.contains(&(0, 0, (1, 0), 0)));
+        assert!(placements.contains(&(1, 0, (1, 0), 0)));
+        assert!(placements.contains(&(1, 1, (1, 0), 0)));
+        assert!(placements.iter().all(|(shop, _, _, _)| *shop < 2));
+        assert_eq!(
+            placements
+                .iter()
+                .filter(|(shop, _, _, _)| *shop == 1)
+                .count(),
+            castle.all_possible_placements(&neutral).len()
+        );
+    }
+
+    #[test]
+    fn test_link_distance() {
+        let vault = room(
+            "Small Vault",
+            1,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Moon(false),
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault.clone(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(vault, (-1, 0), 180))
+            .unwrap();
+        assert_eq!(castle.link_distance((0, 0, 2, 0)).unwrap(), 0);
+        assert_eq!(castle.link_distance((0, 0, 3, 0)).unwrap(), 1);
+        assert_eq!(castle.link_distance((1, 0, 1, 2)).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_replace_room() {
+        let vault = small_vault();
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault, (0, 1), 90))
+            .unwrap();
+        let treasury = room(
+            "Treasury",
+            3,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Moon(true),
+            ],
+        );
+        let renovated = castle.replace_room((0, 1), treasury.clone()).unwrap();
+        assert_eq!(renovated.rooms[&(0, 1)], PlacedRoom::from(treasury, 90));
+        assert_eq!(renovated.get_treasure().unwrap(), 3);
+        // Turned like the vault, the closet's only side would face away from the throne.
+        let closet = room(
+            "Closet",
+            0,
+            [
+                Connection::None,
+                Connection::Cross(false),
+                Connection::None,
+                Connection::None,
+            ],
+        );
+        assert!(matches!(
+            castle.replace_room((0, 1), closet),
+            Err(CastleError::InvalidConnection)
+        ));
+        assert!(matches!(
+            castle.replace_room((5, 5), throne()),
+            Err(CastleError::EmptyPosition)
+        ));
+    }
+
+    #[test]
+    fn test_actions_for_card() {
+        let hall = hall();
+        let vault = small_vault();
+        let shop = vec![hall.clone(), vault];
+        let castle = Castle::new(throne())
+            .apply(Action::Place(hall, (1, 0), 0))
+            .unwrap();
+        let all = castle.possible_actions(&shop);
+        let card = castle.actions_for_card(&shop, 1).unwrap();
+        assert!(!card.is_empty());
+        assert!(card.iter().all(|action| all.contains(action)));
+        assert!(card
+            .iter()
+            .all(|action| matches!(action, Action::Place(room, _, _) if *room == shop[1])));
+        assert!(matches!(
+            castle.actions_for_card(&shop, 2),
+            Err(CastleError::InvalidShopIndex(2))
+        ));
+    }
+
+    #[test]
+    fn test_validate_rotation() {
+        let serialized = ron::to_string(&Castle::new(throne())).unwrap();
+        assert!(serialized.contains("rotation:0"));
+        let castle: Castle =
+            ron::from_str(&serialized.replace("rotation:0", "rotation:37")).unwrap();
+        assert!(matches!(
+            castle.validate(),
+            Err(CastleError::InvalidRotation(37))
+        ));
+        assert!(Castle::new(throne()).validate().is_ok());
+    }
+
+    #[test]
+    fn test_actions_by_target() {
+        let hall = hall();
+        let shop = vec![hall.clone()];
+        let castle = Castle::new(throne())
+            .apply(Action::Place(hall, (1, 0), 0))
+            .unwrap();
+        let by_target = castle.actions_by_target(&shop);
+        let west = &by_target[&(-1, 0)];
+        assert!(west.iter().any(|a| a.kind() == ActionKind::Place));
+        assert!(west.contains(&Action::Move((1, 0), (-1, 0), 0)));
+        assert!(by_target
+            .values()
+            .flatten()
+            .all(|a| matches!(a.kind(), ActionKind::Place | ActionKind::Move)));
+    }
+
+    #[test]
+    fn test_place_action_preview() {
+        let vault = small_vault();
+        let castle = Castle::new(throne());
+        let action = castle.place_action(vault.clone(), (0, 1), 90).unwrap();
+        assert_eq!(action, Action::Place(vault.clone(), (0, 1), 90));
+        let placed = castle.apply(action).unwrap();
+        assert_eq!(placed.rooms[&(0, 1)], PlacedRoom::from(vault.clone(), 90));
+        assert!(matches!(
+            castle.place_action(vault, (0, 1), 180),
+            Err(CastleError::InvalidConnection)
+        ));
+    }
 }
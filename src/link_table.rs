@@ -0,0 +1,180 @@
+use crate::{Castle, CastleError, Connection, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+
+const CONNECTIONS: [Connection; 8] = [
+    Connection::None,
+    Connection::Wild,
+    Connection::Diamond(false),
+    Connection::Diamond(true),
+    Connection::Cross(false),
+    Connection::Cross(true),
+    Connection::Moon(false),
+    Connection::Moon(true),
+];
+
+/*
+ * What a side links as when facing another side. Pairs missing from the
+ * table cannot face each other, like Connection::link returning an error.
+ */
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct LinkTable {
+    #[serde(
+        serialize_with = "serialize_links",
+        deserialize_with = "deserialize_links"
+    )]
+    links: BTreeMap<(Connection, Connection), Connection>,
+}
+
+/*
+ * Links written as a list of { side, other, link } entries, for formats like
+ * JSON that only allow string keys in maps.
+ */
+#[derive(Serialize, Deserialize)]
+struct LinkEntry {
+    side: Connection,
+    other: Connection,
+    link: Connection,
+}
+
+fn serialize_links<S: Serializer>(
+    links: &BTreeMap<(Connection, Connection), Connection>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    links
+        .iter()
+        .map(|((side, other), link)| LinkEntry {
+            side: *side,
+            other: *other,
+            link: *link,
+        })
+        .collect::<Vec<_>>()
+        .serialize(serializer)
+}
+
+fn deserialize_links<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<BTreeMap<(Connection, Connection), Connection>, D::Error> {
+    let entries = Vec::<LinkEntry>::deserialize(deserializer)?;
+    Ok(entries
+        .into_iter()
+        .map(|entry| ((entry.side, entry.other), entry.link))
+        .collect())
+}
+
+impl LinkTable {
+    pub fn standard() -> Self {
+        let mut links = BTreeMap::new();
+        for side in CONNECTIONS.iter() {
+            for other in CONNECTIONS.iter() {
+                if let Ok(link) = side.link(other) {
+                    links.insert((*side, *other), link);
+                }
+            }
+        }
+        LinkTable { links }
+    }
+    /*
+     * Sets the link seen from side when facing other. Only this direction is
+     * changed, call it again with the sides flipped for the other room.
+     */
+    pub fn insert(&mut self, side: Connection, other: Connection, link: Connection) {
+        self.links.insert((side, other), link);
+    }
+    pub fn link(&self, side: &Connection, other: &Connection) -> Result<Connection> {
+        self.links
+            .get(&(*side, *other))
+            .copied()
+            .ok_or(CastleError::InvalidConnection)
+    }
+}
+
+impl Castle {
+    pub fn with_link_table(&self, link_table: LinkTable) -> Castle {
+        let mut castle = self.clone();
+        castle.link_table = Some(link_table);
+        castle
+    }
+    /*
+     * Connection::link, unless the castle carries its own link table
+     */
+    pub(crate) fn link(&self, side: &Connection, other: &Connection) -> Result<Connection> {
+        match &self.link_table {
+            Some(table) => table.link(side, other),
+            None => side.link(other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LinkTable;
+    use crate::tests::{room, throne};
+    use crate::{Action, Castle, Connection};
+
+    #[test]
+    fn test_standard_table() {
+        let table = LinkTable::standard();
+        assert_eq!(
+            table
+                .link(&Connection::Wild, &Connection::Moon(false))
+                .unwrap(),
+            Connection::Moon(true)
+        );
+        assert!(table.link(&Connection::None, &Connection::Wild).is_err());
+    }
+
+    #[test]
+    fn test_table_round_trip() {
+        let castle = Castle::new(throne()).with_link_table(LinkTable::standard());
+        let serialized = ron::to_string(&castle).unwrap();
+        assert!(serialized.contains("link_table:Some((links:[(side:"));
+        assert_eq!(ron::from_str::<Castle>(&serialized).unwrap(), castle);
+    }
+
+    #[test]
+    fn test_custom_table() {
+        let diamond = room(
+            "Diamond Hall",
+            0,
+            [
+                Connection::None,
+                Connection::Diamond(false),
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let moon = room(
+            "Moon Vault",
+            1,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Moon(false),
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(diamond, (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(moon, (2, 0), 0))
+            .unwrap();
+        assert_eq!(castle.get_links().unwrap(), (0, 1, 0, 0));
+
+        let mut table = LinkTable::standard();
+        table.insert(
+            Connection::Diamond(false),
+            Connection::Moon(false),
+            Connection::Diamond(false),
+        );
+        table.insert(
+            Connection::Moon(false),
+            Connection::Diamond(false),
+            Connection::Diamond(false),
+        );
+        assert_eq!(
+            castle.with_link_table(table).get_links().unwrap(),
+            (1, 1, 0, 0)
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use crate::Castle;
+#[cfg(feature = "metrics")]
+use std::cell::Cell;
+
+/*
+ * Call counts of the hot placement checks on the current thread. Nothing is
+ * counted unless the metrics feature is enabled.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct CastleMetrics {
+    pub can_place_room: u64,
+    pub connect: u64,
+}
+
+#[cfg(feature = "metrics")]
+thread_local! {
+    static METRICS: Cell<CastleMetrics> = Cell::new(CastleMetrics::default());
+}
+
+#[inline(always)]
+pub(crate) fn count_can_place_room() {
+    #[cfg(feature = "metrics")]
+    METRICS.with(|metrics| {
+        let mut counts = metrics.get();
+        counts.can_place_room += 1;
+        metrics.set(counts);
+    });
+}
+
+#[inline(always)]
+pub(crate) fn count_connect() {
+    #[cfg(feature = "metrics")]
+    METRICS.with(|metrics| {
+        let mut counts = metrics.get();
+        counts.connect += 1;
+        metrics.set(counts);
+    });
+}
+
+impl Castle {
+    /*
+     * Returns the counts gathered on this thread so far and resets them
+     */
+    pub fn take_metrics() -> CastleMetrics {
+        #[cfg(feature = "metrics")]
+        return METRICS.with(|metrics| metrics.take());
+        #[cfg(not(feature = "metrics"))]
+        CastleMetrics::default()
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use crate::tests::{small_vault, throne};
+    use crate::{Castle, CastleMetrics};
+
+    #[test]
+    fn test_metrics() {
+        let castle = Castle::new(throne());
+        let shop = vec![small_vault()];
+        Castle::take_metrics();
+        castle.possible_actions(&shop);
+        let metrics = Castle::take_metrics();
+        assert!(metrics.can_place_room > 0);
+        assert!(metrics.connect > 0);
+        assert_eq!(Castle::take_metrics(), CastleMetrics::default());
+    }
+}
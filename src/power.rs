@@ -0,0 +1,531 @@
+use crate::{connecting, Castle, CastleError, Connection, Pos, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/*
+ * Local: a room is powered when every side that needs power gets it from its neighbor.
+ * FromThrone: a room is powered when a chain of linked sides leads back to a throne room.
+ * Propagated: a room is powered when a chain of powered links leads back to a power source.
+ */
+#[derive(
+    Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Ord, PartialOrd, Serialize, Deserialize,
+)]
+pub enum PowerModel {
+    Local,
+    FromThrone,
+    #[default]
+    Propagated,
+}
+
+impl Castle {
+    pub fn get_treasure_with(&self, model: PowerModel) -> Result<u32> {
+        let reached = match model {
+            PowerModel::Local => HashSet::new(),
+            PowerModel::FromThrone => self.powered_from_throne(),
+            PowerModel::Propagated => self.powered_rooms(),
+        };
+        let mut treasure = 0;
+        for (pos, room) in self.rooms.iter() {
+            let powered = match model {
+                PowerModel::Local => self.room_is_powered(*pos)?,
+                PowerModel::FromThrone | PowerModel::Propagated => reached.contains(pos),
+            };
+            if room.info.treasure > 0 && powered {
+                treasure += room.info.treasure as u32;
+            }
+        }
+        Ok(treasure)
+    }
+    pub fn room_treasure(&self, pos: Pos) -> Result<u8> {
+        let room = self.rooms.get(&pos).ok_or(CastleError::EmptyPosition)?;
+        if self.powered_rooms().contains(&pos) {
+            Ok(room.info.treasure)
+        } else {
+            Ok(0)
+        }
+    }
+    /*
+     * Rooms with a wild side that powers a colored side of a neighbor
+     */
+    pub fn power_sources(&self) -> Vec<Pos> {
+        self.rooms
+            .iter()
+            .filter(|(pos, room)| {
+                connecting(**pos).iter().enumerate().any(|(i, con_pos)| {
+                    let side = room.get_connections()[i];
+                    match self.rooms.get(con_pos) {
+                        Some(con_room) if side == Connection::Wild => self
+                            .link(&side, &con_room.get_connections()[(i + 2) % 4])
+                            .map(|link| link.power())
+                            .unwrap_or(false),
+                        _ => false,
+                    }
+                })
+            })
+            .map(|(pos, _)| *pos)
+            .collect()
+    }
+    /*
+     * Checks every side marked as powered that faces a neighbor actually gets
+     * power from it, reporting the first one that doesn't. Sides facing empty
+     * positions are not checked.
+     */
+    pub fn check_power_consistency(&self) -> Result<()> {
+        for (pos, room) in self.rooms.iter() {
+            for (i, con_pos) in connecting(*pos).iter().enumerate() {
+                let side = room.get_connections()[i];
+                if let Some(con_room) = self.rooms.get(con_pos) {
+                    let powered = self
+                        .link(&side, &con_room.get_connections()[(i + 2) % 4])
+                        .map(|link| link.power())
+                        .unwrap_or(false);
+                    if side.power() && !powered {
+                        return Err(CastleError::InconsistentPower { pos: *pos, side: i });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    /*
+     * Whether every consecutive pair of rooms along path is linked by a
+     * powered connection, from either side.
+     */
+    pub fn is_path_powered(&self, path: &[Pos]) -> Result<bool> {
+        for pos in path {
+            if !self.rooms.contains_key(pos) {
+                return Err(CastleError::EmptyPosition);
+            }
+        }
+        let mut powered = true;
+        for pair in path.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            let i = connecting(from)
+                .iter()
+                .position(|p| *p == to)
+                .ok_or(CastleError::InvalidPosition)?;
+            let side = self.rooms[&from].get_connections()[i];
+            let con_side = self.rooms[&to].get_connections()[(i + 2) % 4];
+            let powered_link = |a, b| self.link(a, b).map(|l| l.power()).unwrap_or(false);
+            if !powered_link(&side, &con_side) && !powered_link(&con_side, &side) {
+                powered = false;
+            }
+        }
+        Ok(powered)
+    }
+    /*
+     * Pairs of rooms joined by a colored link that neither side powers
+     */
+    pub fn unpowered_connections(&self) -> u8 {
+        let mut count: u8 = 0;
+        for (pos, room) in self.rooms.iter() {
+            for (i, con_pos) in connecting(*pos).iter().enumerate() {
+                if *con_pos <= *pos {
+                    continue;
+                }
+                if let Some(con_room) = self.rooms.get(con_pos) {
+                    let side = room.get_connections()[i];
+                    let con_side = con_room.get_connections()[(i + 2) % 4];
+                    if side.connect(&con_side) != Some(true) {
+                        continue;
+                    }
+                    let colored = |link: &Result<Connection>| {
+                        matches!(
+                            link,
+                            Ok(Connection::Diamond(_))
+                                | Ok(Connection::Cross(_))
+                                | Ok(Connection::Moon(_))
+                        )
+                    };
+                    let links = [self.link(&side, &con_side), self.link(&con_side, &side)];
+                    if links.iter().all(colored)
+                        && !links.iter().any(|link| matches!(link, Ok(l) if l.power()))
+                    {
+                        count = count.saturating_add(1);
+                    }
+                }
+            }
+        }
+        count
+    }
+    /*
+     * Cheap filter for swap evaluation: false only when swapping a and b can't
+     * change the treasure. Only links at a and b change, so power can only
+     * start or stop flowing there if a, b or one of their neighbors is powered
+     * or has a wild side; otherwise only a treasure room among them matters.
+     * A true result does not mean the treasure actually changes.
+     */
+    pub fn swap_affects_power(&self, a: Pos, b: Pos) -> Result<bool> {
+        if !self.rooms.contains_key(&a) || !self.rooms.contains_key(&b) {
+            return Err(CastleError::EmptyPosition);
+        }
+        let nearby: Vec<Pos> = [a, b]
+            .iter()
+            .chain(connecting(a).iter())
+            .chain(connecting(b).iter())
+            .filter(|pos| self.rooms.contains_key(pos))
+            .copied()
+            .collect();
+        if nearby.iter().any(|pos| self.rooms[pos].info.treasure > 0) {
+            return Ok(true);
+        }
+        let powered = self.powered_rooms();
+        let touches_power = nearby.iter().any(|pos| {
+            powered.contains(pos) || self.rooms[pos].info.connections.contains(&Connection::Wild)
+        });
+        Ok(touches_power && self.rooms.values().any(|room| room.info.treasure > 0))
+    }
+    /*
+     * Rooms power flows to: the power sources, then every room joined to a
+     * powered room by a link that carries power from either side.
+     */
+    pub fn powered_rooms(&self) -> HashSet<Pos> {
+        let mut queue = self.power_sources();
+        let mut reached: HashSet<Pos> = queue.iter().copied().collect();
+        while let Some(pos) = queue.pop() {
+            let connections = self.rooms[&pos].get_connections();
+            for (i, con_pos) in connecting(pos).iter().enumerate() {
+                if let Some(con_room) = self.rooms.get(con_pos) {
+                    let side = connections[i];
+                    let con_side = con_room.get_connections()[(i + 2) % 4];
+                    let powered_link = |a, b| self.link(a, b).map(|l| l.power()).unwrap_or(false);
+                    if (powered_link(&side, &con_side) || powered_link(&con_side, &side))
+                        && reached.insert(*con_pos)
+                    {
+                        queue.push(*con_pos);
+                    }
+                }
+            }
+        }
+        reached
+    }
+    fn powered_from_throne(&self) -> HashSet<Pos> {
+        let mut queue: Vec<Pos> = self
+            .rooms
+            .iter()
+            .filter(|(_, room)| room.info.throne)
+            .map(|(pos, _)| *pos)
+            .collect();
+        let mut reached: HashSet<Pos> = queue.iter().copied().collect();
+        while let Some(pos) = queue.pop() {
+            let connections = self.rooms[&pos].get_connections();
+            for (i, con_pos) in connecting(pos).iter().enumerate() {
+                if let Some(con_room) = self.rooms.get(con_pos) {
+                    let linked = matches!(
+                        self.link(&connections[i], &con_room.get_connections()[(i + 2) % 4]),
+                        Ok(link) if link != Connection::None
+                    );
+                    if linked && reached.insert(*con_pos) {
+                        queue.push(*con_pos);
+                    }
+                }
+            }
+        }
+        reached
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{hall, room, small_vault, throne};
+    use crate::{Action, Castle, CastleError, Connection, PlacedRoom, PowerModel};
+
+    fn chain(hall_power: bool) -> Castle {
+        let hall = room(
+            "Hall",
+            0,
+            [
+                Connection::None,
+                Connection::Cross(hall_power),
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let vault = small_vault();
+        Castle::new(throne())
+            .apply(Action::Place(hall, (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(vault, (2, 0), 0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_powered_side_facing_nothing() {
+        let vault = room(
+            "Vault",
+            2,
+            [
+                Connection::None,
+                Connection::Cross(true),
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault, (1, 0), 0))
+            .unwrap();
+        assert_eq!(castle.get_treasure().unwrap(), 2);
+        assert_eq!(castle.get_treasure_with(PowerModel::Local).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_is_path_powered() {
+        let path = [(0, 0), (1, 0), (2, 0)];
+        assert!(chain(true).is_path_powered(&path).unwrap());
+        assert!(!chain(false).is_path_powered(&path).unwrap());
+        assert!(matches!(
+            chain(true).is_path_powered(&[(0, 0), (2, 0)]),
+            Err(CastleError::InvalidPosition)
+        ));
+        assert!(matches!(
+            chain(true).is_path_powered(&[(2, 0), (3, 0)]),
+            Err(CastleError::EmptyPosition)
+        ));
+    }
+
+    #[test]
+    fn test_room_treasure() {
+        assert_eq!(chain(true).room_treasure((2, 0)).unwrap(), 1);
+        assert_eq!(chain(false).room_treasure((2, 0)).unwrap(), 0);
+        assert_eq!(chain(false).room_treasure((1, 0)).unwrap(), 0);
+        assert!(matches!(
+            chain(true).room_treasure((5, 5)),
+            Err(CastleError::EmptyPosition)
+        ));
+    }
+
+    #[test]
+    fn test_get_treasure_no_wrap() {
+        let hoard = room(
+            "Hoard",
+            200,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let mut castle = Castle::new(throne());
+        for (pos, rot) in [((1, 0), 0), ((0, 1), 90), ((-1, 0), 180), ((0, -1), 270)] {
+            castle = castle
+                .apply(Action::Place(hoard.clone(), pos, rot))
+                .unwrap();
+        }
+        assert_eq!(castle.get_treasure().unwrap(), 800);
+        assert_eq!(castle.get_treasure_with(PowerModel::Local).unwrap(), 800);
+    }
+
+    #[test]
+    fn test_powered_rooms() {
+        // Power from the throne only reaches the vault once the hall passes it on.
+        let powered = chain(true).powered_rooms();
+        assert_eq!(powered.len(), 3);
+        assert!(powered.contains(&(2, 0)));
+        assert_eq!(chain(true).get_treasure().unwrap(), 1);
+
+        let unpowered = chain(false).powered_rooms();
+        assert!(unpowered.contains(&(0, 0)) && unpowered.contains(&(1, 0)));
+        assert!(!unpowered.contains(&(2, 0)));
+        assert_eq!(chain(false).get_treasure().unwrap(), 0);
+        assert_eq!(
+            chain(false).get_treasure_with(PowerModel::Local).unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_power_follows_room_edits() {
+        let vault = room(
+            "Vault",
+            3,
+            [
+                Connection::None,
+                Connection::Cross(false),
+                Connection::None,
+                Connection::Cross(true),
+            ],
+        );
+        let hall = room(
+            "Hall",
+            0,
+            [
+                Connection::None,
+                Connection::Moon(false),
+                Connection::None,
+                Connection::Moon(false),
+            ],
+        );
+        let mut castle = Castle::new(throne())
+            .apply(Action::Place(vault, (1, 0), 0))
+            .unwrap();
+        assert_eq!(castle.get_treasure().unwrap(), 3);
+        // Power is worked out from the current neighbors every time, so edits
+        // made straight to the rooms show up without any recomputation.
+        castle.rooms.insert((0, 0), PlacedRoom::from(hall, 0));
+        assert_eq!(castle.get_treasure().unwrap(), 0);
+        castle.rooms.insert((0, 0), PlacedRoom::from(throne(), 0));
+        assert_eq!(castle.get_treasure().unwrap(), 3);
+        castle.rooms.remove(&(0, 0));
+        assert_eq!(castle.get_treasure().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_power_sources() {
+        let vault = room(
+            "Vault",
+            3,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Cross(true),
+            ],
+        );
+        let castle = Castle::new(throne());
+        assert!(castle.power_sources().is_empty());
+        let castle = castle.apply(Action::Place(vault, (1, 0), 0)).unwrap();
+        assert_eq!(castle.power_sources(), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn test_check_power_consistency() {
+        let vault = room(
+            "Vault",
+            3,
+            [
+                Connection::None,
+                Connection::Moon(false),
+                Connection::None,
+                Connection::Cross(true),
+            ],
+        );
+        let hall = room(
+            "Hall",
+            0,
+            [
+                Connection::None,
+                Connection::Diamond(false),
+                Connection::None,
+                Connection::Diamond(false),
+            ],
+        );
+        let mut castle = Castle::new(throne())
+            .apply(Action::Place(vault, (1, 0), 0))
+            .unwrap();
+        assert!(castle.check_power_consistency().is_ok());
+        castle.rooms.insert((0, 0), PlacedRoom::from(hall, 0));
+        assert!(matches!(
+            castle.check_power_consistency(),
+            Err(CastleError::InconsistentPower {
+                pos: (1, 0),
+                side: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_get_treasure_with() {
+        let hall = hall();
+        let vault = room(
+            "Vault",
+            2,
+            [
+                Connection::None,
+                Connection::Cross(true),
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(hall, (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(vault, (2, 0), 0))
+            .unwrap();
+        // The far vault's powered side faces nothing, but it is linked back to the throne.
+        assert_eq!(castle.get_treasure_with(PowerModel::Local).unwrap(), 0);
+        assert_eq!(castle.get_treasure_with(PowerModel::FromThrone).unwrap(), 2);
+        assert_eq!(castle.get_treasure().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_swap_affects_power() {
+        let hall = hall();
+        let castle = chain(false)
+            .apply(Action::Place(hall.clone(), (-1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(hall.clone(), (-2, 0), 0))
+            .unwrap()
+            .apply(Action::Place(hall.clone(), (-3, 0), 0))
+            .unwrap()
+            .apply(Action::Place(hall, (-4, 0), 0))
+            .unwrap();
+        // The vault would end up beside the throne.
+        assert!(castle.swap_affects_power((2, 0), (-1, 0)).unwrap());
+        // The hall beside the throne passes power along.
+        assert!(castle.swap_affects_power((-1, 0), (-2, 0)).unwrap());
+        // No power reaches this far along the unpowered halls.
+        assert!(!castle.swap_affects_power((-3, 0), (-4, 0)).unwrap());
+        assert!(matches!(
+            castle.swap_affects_power((-1, 0), (5, 5)),
+            Err(CastleError::EmptyPosition)
+        ));
+    }
+
+    #[test]
+    fn test_swap_affects_power_chain() {
+        let hot = room(
+            "Hot",
+            0,
+            [
+                Connection::None,
+                Connection::Cross(true),
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let cold = room(
+            "Cold",
+            0,
+            [
+                Connection::None,
+                Connection::Cross(false),
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let vault = room(
+            "Vault",
+            3,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(hot.clone(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(hot.clone(), (2, 0), 0))
+            .unwrap()
+            .apply(Action::Place(hot, (3, 0), 0))
+            .unwrap()
+            .apply(Action::Place(vault, (4, 0), 0))
+            .unwrap()
+            .apply(Action::Place(cold, (-1, 0), 0))
+            .unwrap();
+        assert_eq!(castle.get_treasure().unwrap(), 3);
+        // The vault is two rooms away from either end of the swap.
+        let swapped = castle.apply(Action::Swap((2, 0), (-1, 0))).unwrap();
+        assert_eq!(swapped.get_treasure().unwrap(), 0);
+        assert!(castle.swap_affects_power((2, 0), (-1, 0)).unwrap());
+    }
+
+    #[test]
+    fn test_unpowered_connections() {
+        assert_eq!(chain(false).unpowered_connections(), 1);
+        assert_eq!(chain(true).unpowered_connections(), 0);
+        assert_eq!(Castle::new(throne()).unpowered_connections(), 0);
+    }
+}
@@ -0,0 +1,260 @@
+use crate::{connecting, Castle, Connection, PlacedRoom, Pos, Result};
+use std::{collections::HashSet, fmt};
+
+/*
+ * Overlays for Castle::render, all off by default
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct RenderOptions {
+    pub show_links: bool,
+    pub show_power: bool,
+    pub show_treasure: bool,
+}
+
+impl Castle {
+    /*
+     * One line listing every room as position:tag, with @rotation when
+     * rotated. Thrones are tagged T, other rooms by their name without spaces.
+     */
+    pub fn debug_compact(&self) -> String {
+        let rooms: Vec<String> = self
+            .rooms
+            .iter()
+            .map(|((x, y), room)| {
+                let tag = if room.info.throne {
+                    "T".to_string()
+                } else {
+                    room.info.name.split_whitespace().collect()
+                };
+                if room.rotation == 0 {
+                    format!("({},{}):{}", x, y, tag)
+                } else {
+                    format!("({},{}):{}@{}", x, y, tag, room.rotation)
+                }
+            })
+            .collect();
+        format!("{{{}}} dmg={}", rooms.join(","), self.damage)
+    }
+    /*
+     * Grid of the castle, one three character cell per position: the room's
+     * initial (T for thrones), then * when powered and the treasure digit if
+     * those overlays are on. Link glyphs go between cells when show_links is on.
+     */
+    pub fn render(&self, opts: RenderOptions) -> String {
+        let ((min_x, min_y), (max_x, max_y)) = match self.bounding_box() {
+            Some(bounds) => bounds,
+            None => return String::new(),
+        };
+        let powered = self.powered_rooms();
+        let mut lines = Vec::new();
+        for y in min_y..=max_y {
+            let mut cells = String::new();
+            let mut below = String::new();
+            for x in min_x..=max_x {
+                cells.push_str(&self.render_cell((x, y), &powered, opts));
+                below.push(self.link_glyph((x, y), 2, opts));
+                below.push_str("   ");
+                if x < max_x {
+                    cells.push(self.link_glyph((x, y), 1, opts));
+                }
+            }
+            lines.push(cells.trim_end().to_string());
+            if y < max_y {
+                lines.push(below.trim_end().to_string());
+            }
+        }
+        lines.join("\n")
+    }
+    fn render_cell(&self, pos: Pos, powered: &HashSet<Pos>, opts: RenderOptions) -> String {
+        let room = match self.rooms.get(&pos) {
+            Some(room) => room,
+            None => return "   ".to_string(),
+        };
+        let initial = initial(room);
+        let power = if opts.show_power && powered.contains(&pos) {
+            '*'
+        } else {
+            ' '
+        };
+        let treasure = if opts.show_treasure && room.info.treasure > 0 {
+            std::char::from_digit(room.info.treasure.min(9) as u32, 10).unwrap()
+        } else {
+            ' '
+        };
+        format!("{}{}{}", initial, power, treasure)
+    }
+    /*
+     * Glyph for the link on side i of pos, blank when there is none to show
+     */
+    fn link_glyph(&self, pos: Pos, i: usize, opts: RenderOptions) -> char {
+        let con_pos = connecting(pos)[i];
+        let (room, con_room) = match (self.rooms.get(&pos), self.rooms.get(&con_pos)) {
+            (Some(room), Some(con_room)) if opts.show_links => (room, con_room),
+            _ => return ' ',
+        };
+        self.link(
+            &room.get_connections()[i],
+            &con_room.get_connections()[(i + 2) % 4],
+        )
+        .map_or(' ', |link| glyph(&link))
+    }
+    pub fn links_summary(&self) -> Result<String> {
+        let (diamond, cross, moon, wild) = self.get_links()?;
+        Ok(format!(
+            "{}{} {}{} {}{} {}{} | dmg {}/{}",
+            glyph(&Connection::Diamond(false)),
+            diamond,
+            glyph(&Connection::Cross(false)),
+            cross,
+            glyph(&Connection::Moon(false)),
+            moon,
+            glyph(&Connection::Wild),
+            wild,
+            self.damage,
+            self.rooms.len()
+        ))
+    }
+}
+
+/*
+ * Each room drawn as a three by three block: its initial in the middle with
+ * the glyph of each rotated side around it. Empty positions are left blank.
+ */
+impl fmt::Display for Castle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ((min_x, min_y), (max_x, max_y)) = match self.bounding_box() {
+            Some(bounds) => bounds,
+            None => return Ok(()),
+        };
+        let mut lines = Vec::new();
+        for y in min_y..=max_y {
+            let mut rows = [String::new(), String::new(), String::new()];
+            for x in min_x..=max_x {
+                match self.rooms.get(&(x, y)) {
+                    Some(room) => {
+                        let [north, east, south, west] =
+                            room.get_connections().map(|side| glyph(&side));
+                        rows[0].extend([' ', north, ' ']);
+                        rows[1].extend([west, initial(room), east]);
+                        rows[2].extend([' ', south, ' ']);
+                    }
+                    None => rows.iter_mut().for_each(|row| row.push_str("   ")),
+                }
+            }
+            lines.extend(rows.iter().map(|row| row.trim_end().to_string()));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+fn initial(room: &PlacedRoom) -> char {
+    if room.info.throne {
+        'T'
+    } else {
+        room.info.name.chars().next().unwrap_or('?')
+    }
+}
+
+/*
+ * The one glyph for each kind of side, shared by every rendering
+ */
+fn glyph(side: &Connection) -> char {
+    match side {
+        Connection::None => ' ',
+        Connection::Wild => '*',
+        Connection::Diamond(_) => '◇',
+        Connection::Cross(_) => '✚',
+        Connection::Moon(_) => '☾',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{room, small_vault, throne};
+    use crate::{Action, Castle, Connection, RenderOptions};
+
+    fn castle() -> Castle {
+        let vault = room(
+            "Small Vault",
+            2,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Cross(true),
+            ],
+        );
+        let gallery = room(
+            "Gallery",
+            0,
+            [
+                Connection::Moon(false),
+                Connection::None,
+                Connection::None,
+                Connection::None,
+            ],
+        );
+        Castle::new(throne())
+            .apply(Action::Place(vault, (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(gallery, (0, 1), 0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_render_plain() {
+        assert_eq!(castle().render(RenderOptions::default()), "T   S\n\nG");
+        assert_eq!(Castle::new_empty().render(RenderOptions::default()), "");
+    }
+
+    #[test]
+    fn test_render_overlays() {
+        let links = RenderOptions {
+            show_links: true,
+            ..RenderOptions::default()
+        };
+        assert_eq!(castle().render(links), "T  ✚S\n☾\nG");
+        let power = RenderOptions {
+            show_power: true,
+            ..RenderOptions::default()
+        };
+        assert_eq!(castle().render(power), "T*  S*\n\nG*");
+        let treasure = RenderOptions {
+            show_treasure: true,
+            ..RenderOptions::default()
+        };
+        assert_eq!(castle().render(treasure), "T   S 2\n\nG");
+    }
+
+    #[test]
+    fn test_display() {
+        let vault = small_vault();
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault, (1, 0), 0))
+            .unwrap();
+        assert_eq!(castle.to_string(), " *\n*T*✚S\n *");
+        assert_eq!(Castle::new_empty().to_string(), "");
+    }
+
+    #[test]
+    fn test_links_summary() {
+        let vault = small_vault();
+        let mut castle = Castle::new(throne())
+            .apply(Action::Place(vault, (1, 0), 0))
+            .unwrap();
+        castle.damage = 1;
+        assert_eq!(castle.links_summary().unwrap(), "◇0 ✚1 ☾0 *0 | dmg 1/2");
+    }
+
+    #[test]
+    fn test_debug_compact() {
+        let vault = small_vault();
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault, (0, -1), 270))
+            .unwrap();
+        assert_eq!(
+            castle.debug_compact(),
+            "{(0,-1):SmallVault@270,(0,0):T} dmg=0"
+        );
+    }
+}
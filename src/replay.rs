@@ -0,0 +1,241 @@
+use crate::{Action, ActionKind, Castle, CastleError, Result, Room};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+pub struct LogStats {
+    pub total_actions: usize,
+    pub placements: usize,
+    pub moves: usize,
+    pub swaps: usize,
+    pub discards: usize,
+    pub max_rooms: usize,
+    pub final_treasure: u32,
+    pub ever_lost: bool,
+}
+
+/*
+ * A starting castle and the actions applied to it so far, for undo and saved
+ * games. The current castle is kept so it doesn't need replaying on every read.
+ * Only the start and the actions are saved; loading replays them and fails if
+ * one of them doesn't apply.
+ */
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "SavedGameLog")]
+pub struct GameLog {
+    start: Castle,
+    actions: Vec<Action>,
+    #[serde(skip_serializing)]
+    current: Castle,
+}
+
+#[derive(Deserialize)]
+struct SavedGameLog {
+    start: Castle,
+    actions: Vec<Action>,
+}
+
+impl TryFrom<SavedGameLog> for GameLog {
+    type Error = CastleError;
+
+    fn try_from(saved: SavedGameLog) -> Result<Self> {
+        GameLog::from_actions(saved.start, saved.actions)
+    }
+}
+
+impl GameLog {
+    pub fn new(start: Castle) -> Self {
+        GameLog {
+            current: start.clone(),
+            start,
+            actions: Vec::new(),
+        }
+    }
+    /*
+     * A log of actions already taken from start, replayed to find the current
+     * castle. Fails with the error of the first action that doesn't apply.
+     */
+    pub fn from_actions(start: Castle, actions: Vec<Action>) -> Result<Self> {
+        let current = GameLog::replay(&start, &actions)?;
+        Ok(GameLog {
+            start,
+            actions,
+            current,
+        })
+    }
+    pub fn current(&self) -> &Castle {
+        &self.current
+    }
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+    /*
+     * Applies action to the current castle and records it. A failed action
+     * leaves the log as it was.
+     */
+    pub fn push(&mut self, action: Action) -> Result<&Castle> {
+        self.current = self.current.apply(action.clone())?;
+        self.actions.push(action);
+        Ok(&self.current)
+    }
+    /*
+     * Drops the last action and replays the rest from the start. Returns the
+     * castle after the undo, or None when there is nothing to undo or the
+     * rest no longer replays, in which case the log is left as it was.
+     */
+    pub fn undo(&mut self) -> Option<Castle> {
+        let last = self.actions.pop()?;
+        match GameLog::replay(&self.start, &self.actions) {
+            Ok(castle) => {
+                self.current = castle;
+                Some(self.current.clone())
+            }
+            Err(_) => {
+                self.actions.push(last);
+                None
+            }
+        }
+    }
+    fn replay(start: &Castle, actions: &[Action]) -> Result<Castle> {
+        let mut castle = start.clone();
+        for action in actions {
+            castle = castle.apply(action.clone())?;
+        }
+        Ok(castle)
+    }
+}
+
+impl Castle {
+    /*
+     * The castle after applying every action of log in order, starting from
+     * just the throne. Stops at the first action that fails.
+     */
+    pub fn replay(throne: Room, log: &[Action]) -> Result<Castle> {
+        Castle::replay_with(throne, log, |_, _| ())
+    }
+    pub fn log_stats(throne: Room, log: &[Action]) -> Result<LogStats> {
+        let mut stats = LogStats {
+            total_actions: log.len(),
+            max_rooms: 1,
+            ..LogStats::default()
+        };
+        let castle = Castle::replay_with(throne, log, |action, castle| {
+            match action.kind() {
+                ActionKind::Place => stats.placements += 1,
+                ActionKind::Move => stats.moves += 1,
+                ActionKind::Swap => stats.swaps += 1,
+                ActionKind::Discard => stats.discards += 1,
+                ActionKind::Damage => (),
+            }
+            stats.max_rooms = stats.max_rooms.max(castle.rooms.len());
+            stats.ever_lost |= castle.is_lost();
+        })?;
+        stats.final_treasure = castle.get_treasure()?;
+        Ok(stats)
+    }
+    fn replay_with(
+        throne: Room,
+        log: &[Action],
+        mut inspect: impl FnMut(&Action, &Castle),
+    ) -> Result<Castle> {
+        let mut castle = Castle::new(throne);
+        for action in log {
+            castle = castle.apply(action.clone())?;
+            inspect(action, &castle);
+        }
+        Ok(castle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{hall, room, throne};
+    use crate::{Action, Castle, CastleError, Connection, GameLog, LogStats};
+
+    #[test]
+    fn test_game_log() {
+        let hall = hall();
+        let start = Castle::new(throne());
+        let mut log = GameLog::new(start.clone());
+        let placed = log
+            .push(Action::Place(hall.clone(), (1, 0), 0))
+            .unwrap()
+            .clone();
+        log.push(Action::Move((1, 0), (0, 1), 90)).unwrap();
+        assert!(log.push(Action::Place(hall, (5, 5), 0)).is_err());
+        assert_eq!(log.actions().len(), 2);
+
+        let saved = ron::to_string(&log).unwrap();
+        assert!(!saved.contains("current"));
+        assert_eq!(ron::from_str::<GameLog>(&saved).unwrap(), log);
+        assert_eq!(
+            GameLog::from_actions(start.clone(), log.actions().to_vec()).unwrap(),
+            log
+        );
+
+        assert_eq!(log.undo(), Some(placed.clone()));
+        assert_eq!(log.current(), &placed);
+        assert_eq!(log.undo(), Some(start.clone()));
+        assert_eq!(log.undo(), None);
+        assert_eq!(log.current(), &start);
+        assert!(log.actions().is_empty());
+    }
+
+    #[test]
+    fn test_game_log_rejects_bad_actions() {
+        let hall = hall();
+        let start = Castle::new(throne());
+        let mut log = GameLog::new(start.clone());
+        log.push(Action::Place(hall.clone(), (1, 0), 0)).unwrap();
+        let saved = ron::to_string(&log).unwrap();
+        let far = ron::to_string(&Action::Place(hall.clone(), (9, 9), 0)).unwrap();
+        let near = ron::to_string(&Action::Place(hall.clone(), (1, 0), 0)).unwrap();
+        assert!(saved.contains(&near));
+        assert!(ron::from_str::<GameLog>(&saved.replace(&near, &far)).is_err());
+        assert!(matches!(
+            GameLog::from_actions(start, vec![Action::Place(hall, (9, 9), 0)]),
+            Err(CastleError::InvalidConnection)
+        ));
+    }
+
+    #[test]
+    fn test_log_stats() {
+        let hall = hall();
+        let vault = room(
+            "Vault",
+            2,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Cross(true),
+            ],
+        );
+        let log = vec![
+            Action::Place(hall.clone(), (1, 0), 0),
+            Action::Place(hall, (-1, 0), 0),
+            Action::Place(vault, (0, 1), 90),
+            Action::Swap((1, 0), (-1, 0)),
+            Action::Move((1, 0), (0, -1), 90),
+            Action::Damage(0, 4, 0),
+        ];
+        assert_eq!(
+            Castle::log_stats(throne(), &log).unwrap(),
+            LogStats {
+                total_actions: 6,
+                placements: 3,
+                moves: 1,
+                swaps: 1,
+                discards: 0,
+                max_rooms: 4,
+                final_treasure: 2,
+                ever_lost: false,
+            }
+        );
+        assert_eq!(Castle::replay(throne(), &log).unwrap().damage, 1);
+        assert!(matches!(
+            Castle::log_stats(throne(), &[Action::Discard(vec![(0, 0)])]),
+            Err(CastleError::WrongPhase)
+        ));
+    }
+}
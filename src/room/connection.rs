@@ -1,8 +1,19 @@
 use crate::error::CastleError;
-use serde::{Deserialize, Serialize};
-use std::hash::Hash;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{hash::Hash, str::FromStr};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Ord, PartialOrd)]
+/*
+ * The bool marks a side printed with the power symbol, which carries power
+ * across its link: power flows from a wild side into a matching side, then on
+ * through every link where either side carries power. Only PowerModel::Local
+ * reads it the other way round, as a side that must be fed for the room to be
+ * powered. It is part of the room itself, never cached from the castle, so
+ * editing Castle::rooms can't leave it stale.
+ *
+ * Serialized as a short string: "none", "wild", or the symbol name with a
+ * trailing "+" when powered, e.g. "diamond+".
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd)]
 pub enum Connection {
     None,
     Wild,
@@ -13,6 +24,7 @@ pub enum Connection {
 
 impl Connection {
     pub fn connect(&self, other: &Connection) -> Option<bool> {
+        crate::metrics::count_connect();
         if matches!(self, Connection::None) && matches!(other, Connection::None) {
             return None;
         }
@@ -49,6 +61,53 @@ impl Connection {
     }
 }
 
+impl Connection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Connection::None => "none",
+            Connection::Wild => "wild",
+            Connection::Diamond(false) => "diamond",
+            Connection::Diamond(true) => "diamond+",
+            Connection::Cross(false) => "cross",
+            Connection::Cross(true) => "cross+",
+            Connection::Moon(false) => "moon",
+            Connection::Moon(true) => "moon+",
+        }
+    }
+}
+
+impl FromStr for Connection {
+    type Err = CastleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Connection::None),
+            "wild" => Ok(Connection::Wild),
+            "diamond" => Ok(Connection::Diamond(false)),
+            "diamond+" => Ok(Connection::Diamond(true)),
+            "cross" => Ok(Connection::Cross(false)),
+            "cross+" => Ok(Connection::Cross(true)),
+            "moon" => Ok(Connection::Moon(false)),
+            "moon+" => Ok(Connection::Moon(true)),
+            _ => Err(CastleError::InvalidFormat),
+        }
+    }
+}
+
+impl Serialize for Connection {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Connection {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|_| de::Error::custom(format!("unknown connection \"{}\"", s)))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -63,4 +122,31 @@ mod tests {
         assert!(Connection::None.connect(&Connection::Cross(true)).is_some());
         assert!(Connection::None.connect(&Connection::Moon(true)).is_some());
     }
+
+    #[test]
+    fn test_string_round_trip() {
+        let connections = [
+            Connection::None,
+            Connection::Wild,
+            Connection::Diamond(false),
+            Connection::Diamond(true),
+            Connection::Cross(false),
+            Connection::Cross(true),
+            Connection::Moon(false),
+            Connection::Moon(true),
+        ];
+        for connection in connections.iter() {
+            let serialized = ron::to_string(connection).unwrap();
+            assert_eq!(serialized, format!("\"{}\"", connection.as_str()));
+            assert_eq!(
+                ron::from_str::<Connection>(&serialized).unwrap(),
+                *connection
+            );
+        }
+        assert_eq!(
+            ron::to_string(&Connection::Moon(true)).unwrap(),
+            "\"moon+\""
+        );
+        assert!(ron::from_str::<Connection>("\"star\"").is_err());
+    }
 }
@@ -0,0 +1,20 @@
+use std::{error::Error, fmt};
+
+#[derive(Debug)]
+pub enum RoomError {
+    InvalidRotation(u16),
+}
+
+impl fmt::Display for RoomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoomError::InvalidRotation(rotation) => write!(
+                f,
+                "Rotation of {} degrees is not a multiple of 90.",
+                rotation
+            ),
+        }
+    }
+}
+
+impl Error for RoomError {}
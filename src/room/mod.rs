@@ -1,6 +1,8 @@
 pub mod connection;
+pub mod error;
 
 use connection::Connection;
+use error::RoomError;
 use serde::{Deserialize, Serialize};
 
 use std::{clone::Clone, convert::TryInto, fmt, hash::Hash};
@@ -25,6 +27,65 @@ impl Room {
             .collect();
         connections.try_into().unwrap()
     }
+    /*
+     * Like get_rotated_connections, but only accepts 0, 90, 180 or 270 degrees
+     */
+    pub fn get_rotated_connections_checked(
+        &self,
+        rotation: u16,
+    ) -> Result<[Connection; 4], RoomError> {
+        if !rotation.is_multiple_of(90) || rotation >= 360 {
+            return Err(RoomError::InvalidRotation(rotation));
+        }
+        Ok(self.get_rotated_connections(rotation))
+    }
+    /*
+     * The quarter turns that give different sides, the smallest of any that
+     * give the same
+     */
+    pub fn distinct_rotations(&self) -> Vec<u16> {
+        let mut rotations: Vec<u16> = Vec::new();
+        for rot in [0, 90, 180, 270] {
+            let connections = self.get_rotated_connections(rot);
+            if rotations
+                .iter()
+                .all(|seen| self.get_rotated_connections(*seen) != connections)
+            {
+                rotations.push(rot);
+            }
+        }
+        rotations
+    }
+    /*
+     * Whether other plays the same as this room in some rotation, whatever its name
+     */
+    pub fn same_function(&self, other: &Room) -> bool {
+        self.throne == other.throne
+            && self.treasure == other.treasure
+            && [0, 90, 180, 270]
+                .iter()
+                .any(|rot| self.get_rotated_connections(*rot) == other.connections)
+    }
+    /*
+     * Reflects the room left to right, swapping its east and west connections
+     */
+    pub fn mirror_horizontal(&self) -> Room {
+        let [north, east, south, west] = self.connections;
+        Room {
+            connections: [north, west, south, east],
+            ..self.clone()
+        }
+    }
+    /*
+     * Reflects the room top to bottom, swapping its north and south connections
+     */
+    pub fn mirror_vertical(&self) -> Room {
+        let [north, east, south, west] = self.connections;
+        Room {
+            connections: [south, east, north, west],
+            ..self.clone()
+        }
+    }
 }
 
 impl fmt::Display for Room {
@@ -35,3 +96,102 @@ impl fmt::Display for Room {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mirror() {
+        let room = Room {
+            name: "Small Vault".to_string(),
+            throne: false,
+            treasure: 1,
+            connections: [
+                Connection::None,
+                Connection::Diamond(true),
+                Connection::None,
+                Connection::None,
+            ],
+        };
+        let mirrored = room.mirror_horizontal();
+        assert_eq!(
+            mirrored.connections,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Diamond(true)
+            ]
+        );
+        assert_eq!(mirrored.name, room.name);
+        assert_eq!(mirrored.treasure, room.treasure);
+        assert_eq!(room.mirror_vertical(), room);
+        assert_eq!(mirrored.mirror_horizontal(), room);
+    }
+
+    #[test]
+    fn test_get_rotated_connections_checked() {
+        let room = Room {
+            name: "Small Vault".to_string(),
+            throne: false,
+            treasure: 1,
+            connections: [
+                Connection::None,
+                Connection::Diamond(true),
+                Connection::None,
+                Connection::None,
+            ],
+        };
+        for rot in [0, 90, 180, 270] {
+            assert_eq!(
+                room.get_rotated_connections_checked(rot).unwrap(),
+                room.get_rotated_connections(rot)
+            );
+        }
+        for rot in [45, 360, 450] {
+            assert!(matches!(
+                room.get_rotated_connections_checked(rot),
+                Err(RoomError::InvalidRotation(r)) if r == rot
+            ));
+        }
+        assert_eq!(
+            room.get_rotated_connections(45),
+            room.get_rotated_connections(0)
+        );
+    }
+
+    #[test]
+    fn test_same_function() {
+        let room = Room {
+            name: "Small Vault".to_string(),
+            throne: false,
+            treasure: 1,
+            connections: [
+                Connection::None,
+                Connection::Diamond(true),
+                Connection::None,
+                Connection::None,
+            ],
+        };
+        let renamed = Room {
+            name: "Vault".to_string(),
+            connections: room.get_rotated_connections(90),
+            ..room.clone()
+        };
+        assert!(room.same_function(&renamed));
+        assert!(!room.same_function(&Room {
+            connections: [
+                Connection::None,
+                Connection::Diamond(false),
+                Connection::None,
+                Connection::None,
+            ],
+            ..room.clone()
+        }));
+        assert!(!room.same_function(&Room {
+            treasure: 2,
+            ..room.clone()
+        }));
+    }
+}
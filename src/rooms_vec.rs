@@ -0,0 +1,57 @@
+use crate::{PlacedRoom, Pos};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+
+/*
+ * Rooms written as a list of { pos, room } entries sorted by position, for
+ * formats like JSON that only allow string keys in maps.
+ */
+#[derive(Serialize, Deserialize)]
+struct RoomEntry {
+    pos: [i8; 2],
+    room: PlacedRoom,
+}
+
+pub(crate) fn serialize<S: Serializer>(
+    rooms: &BTreeMap<Pos, PlacedRoom>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    rooms
+        .iter()
+        .map(|((x, y), room)| RoomEntry {
+            pos: [*x, *y],
+            room: room.clone(),
+        })
+        .collect::<Vec<_>>()
+        .serialize(serializer)
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<Pos, PlacedRoom>, D::Error> {
+    let entries = Vec::<RoomEntry>::deserialize(deserializer)?;
+    Ok(entries
+        .into_iter()
+        .map(|entry| ((entry.pos[0], entry.pos[1]), entry.room))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{small_vault, throne};
+    use crate::{Action, Castle};
+
+    #[test]
+    fn test_rooms_vec_round_trip() {
+        let vault = small_vault();
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault.clone(), (0, 1), 90))
+            .unwrap()
+            .apply(Action::Place(vault, (-1, 0), 180))
+            .unwrap();
+        let serialized = ron::to_string(&castle).unwrap();
+        assert!(serialized.starts_with("(rooms:[(pos:(-1,0),room:"));
+        assert!(serialized.find("pos:(0,0)") < serialized.find("pos:(0,1)"));
+        assert_eq!(ron::from_str::<Castle>(&serialized).unwrap(), castle);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{Castle, Result};
+use serde::{Deserialize, Serialize};
+
+/*
+ * Points scored for each link of a kind at the end of the game
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct ScoreConfig {
+    pub diamond: u32,
+    pub cross: u32,
+    pub moon: u32,
+    pub wild: u32,
+}
+
+impl Default for ScoreConfig {
+    fn default() -> Self {
+        ScoreConfig {
+            diamond: 1,
+            cross: 1,
+            moon: 1,
+            wild: 1,
+        }
+    }
+}
+
+impl Castle {
+    pub fn score(&self) -> Result<u32> {
+        self.score_with(&ScoreConfig::default())
+    }
+    /*
+     * Treasure of the powered rooms plus the points config gives each link.
+     * Saturates at u32::MAX rather than overflowing on huge weights.
+     */
+    pub fn score_with(&self, config: &ScoreConfig) -> Result<u32> {
+        let (diamond, cross, moon, wild) = self.get_links()?;
+        Ok([
+            (diamond, config.diamond),
+            (cross, config.cross),
+            (moon, config.moon),
+            (wild, config.wild),
+        ]
+        .iter()
+        .fold(self.get_treasure()?, |score, &(links, points)| {
+            score.saturating_add((links as u32).saturating_mul(points))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{hall, room, throne};
+    use crate::{Action, Castle, Connection, ScoreConfig};
+
+    #[test]
+    fn test_score() {
+        let vault = room(
+            "Small Vault",
+            2,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Cross(true),
+            ],
+        );
+        let hall = hall();
+        // Nothing powers the link between the hall and the treasury.
+        let treasury = room(
+            "Treasury",
+            3,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let gallery = room(
+            "Gallery",
+            0,
+            [
+                Connection::Moon(false),
+                Connection::None,
+                Connection::None,
+                Connection::None,
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault, (-1, 0), 180))
+            .unwrap()
+            .apply(Action::Place(hall, (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(treasury, (2, 0), 0))
+            .unwrap()
+            .apply(Action::Place(gallery, (0, 1), 0))
+            .unwrap();
+        assert_eq!(castle.get_treasure().unwrap(), 2);
+        assert_eq!(castle.get_links().unwrap(), (0, 3, 1, 0));
+        assert_eq!(castle.score().unwrap(), 2 + 4);
+        let config = ScoreConfig {
+            diamond: 5,
+            cross: 2,
+            moon: 3,
+            wild: 0,
+        };
+        assert_eq!(castle.score_with(&config).unwrap(), 2 + 6 + 3);
+        assert_eq!(Castle::new(throne()).score().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_score_saturates() {
+        let castle = Castle::new(throne())
+            .apply(Action::Place(hall(), (1, 0), 0))
+            .unwrap();
+        let config = ScoreConfig {
+            diamond: u32::MAX,
+            cross: u32::MAX,
+            moon: u32::MAX,
+            wild: u32::MAX,
+        };
+        assert_eq!(castle.score_with(&config).unwrap(), u32::MAX);
+    }
+}
@@ -0,0 +1,583 @@
+use crate::{Action, Castle, CastleError, PlacedRoom, Pos, Result, Room, Rot};
+use std::collections::{BTreeSet, VecDeque};
+
+// Caps the number of castles visited by max_expansion.
+const EXPANSION_BUDGET: usize = 10_000;
+// Caps how many cards deep distinct_reachable searches.
+const MAX_DISTINCT_DEPTH: u8 = 3;
+// Caps how many actions deep best_action looks.
+const MAX_BEST_ACTION_DEPTH: u8 = 3;
+
+impl Castle {
+    /*
+     * Most rooms from the shop that can be added, each card used at most once.
+     * Placement orders are searched depth first in shop, rotation and position
+     * order, so the result is deterministic. The search is exhaustive for small
+     * shops; once EXPANSION_BUDGET castles have been visited it returns the best
+     * count found so far, which can underestimate the true maximum.
+     */
+    pub fn max_expansion(&self, shop: &[Room]) -> usize {
+        let mut used = vec![false; shop.len()];
+        let mut visited = 0;
+        self.expand(shop, &mut used, &mut visited)
+    }
+    /*
+     * Every position the outer room at from can end up in after at most depth
+     * moves, in any rotation. The starting position itself is only included
+     * when the room can leave and come back.
+     */
+    pub fn reachable_by_moves(&self, from: Pos, depth: u8) -> Result<BTreeSet<Pos>> {
+        let room = self.rooms.get(&from).ok_or(CastleError::EmptyPosition)?;
+        if !self.room_is_outer(from)? {
+            return Err(CastleError::NotOuterRoom);
+        }
+        let mut reachable = BTreeSet::new();
+        let mut visited: BTreeSet<(Pos, Rot)> = BTreeSet::new();
+        visited.insert((from, room.rotation));
+        let mut queue = VecDeque::new();
+        queue.push_back((self.clone(), from, 0));
+        while let Some((castle, pos, steps)) = queue.pop_front() {
+            if steps == depth {
+                continue;
+            }
+            for rot in [0, 90, 180, 270] {
+                for to in castle.possible_moves(pos, rot) {
+                    reachable.insert(to);
+                    if visited.insert((to, rot)) {
+                        let mut next = castle.clone();
+                        let room = next.rooms.remove(&pos).unwrap();
+                        next.rooms.insert(to, room.rotate(rot));
+                        queue.push_back((next, to, steps + 1));
+                    }
+                }
+            }
+        }
+        Ok(reachable)
+    }
+    /*
+     * The legal swap adding the most links, with how many it adds. Ties go to
+     * the first pair in position order, and None is returned if no swap helps.
+     * Fails where total_links fails on the castle or a swapped one.
+     */
+    pub fn best_swap(&self) -> Result<Option<(Pos, Pos, i16)>> {
+        let before = self.total_links()? as i16;
+        let mut best = None;
+        for pos_1 in self.rooms.keys() {
+            for pos_2 in self.possible_swaps(*pos_1) {
+                if *pos_1 > pos_2 {
+                    continue;
+                }
+                if let Ok(castle) = self.apply(Action::Swap(*pos_1, pos_2)) {
+                    let delta = castle.total_links()? as i16 - before;
+                    if delta > best.map_or(0, |(_, _, d)| d) {
+                        best = Some((*pos_1, pos_2, delta));
+                    }
+                }
+            }
+        }
+        Ok(best)
+    }
+    /*
+     * The placement from the shop adding the most links, or None if none adds
+     * any. Ties go to the first placement in shop, rotation and position order.
+     * Fails where total_links fails on the castle or one with the room placed.
+     */
+    pub fn cheapest_new_link(&self, shop: &[Room]) -> Result<Option<(usize, Pos, Rot)>> {
+        let before = self.total_links()? as i16;
+        let mut best = None;
+        let mut best_delta = 0;
+        for (i, pos, rot) in self.all_possible_placements(shop) {
+            let mut castle = self.clone();
+            castle
+                .rooms
+                .insert(pos, PlacedRoom::from(shop[i].clone(), rot));
+            let delta = castle.total_links()? as i16 - before;
+            if delta > best_delta {
+                best = Some((i, pos, rot));
+                best_delta = delta;
+            }
+        }
+        Ok(best)
+    }
+    /*
+     * The placement from the shop gaining the most treasure, even if that is
+     * none. Ties go to the first placement in shop, rotation and position order.
+     * None while the castle owes damage, since nothing can be built then.
+     */
+    pub fn best_treasure_placement(&self, shop: &[Room]) -> Option<(usize, Pos, Rot)> {
+        if self.damage > 0 {
+            return None;
+        }
+        let before = self.get_treasure().ok()?;
+        let mut best: Option<((usize, Pos, Rot), u32)> = None;
+        for (i, pos, rot) in self.all_possible_placements(shop) {
+            let mut castle = self.clone();
+            castle
+                .rooms
+                .insert(pos, PlacedRoom::from(shop[i].clone(), rot));
+            let gain = match castle.get_treasure() {
+                Ok(treasure) => treasure.saturating_sub(before),
+                Err(_) => continue,
+            };
+            if best.is_none_or(|(_, best_gain)| gain > best_gain) {
+                best = Some(((i, pos, rot), gain));
+            }
+        }
+        best.map(|(placement, _)| placement)
+    }
+    /*
+     * Keeps placing the best treasure placement, each card at most once,
+     * until no card left fits. Returns the shop indices in the order used.
+     * A castle owing damage is returned as it is.
+     */
+    pub fn greedy_fill(&self, shop: &[Room]) -> (Castle, Vec<usize>) {
+        let mut castle = self.clone();
+        let mut remaining: Vec<usize> = (0..shop.len()).collect();
+        let mut used = Vec::new();
+        loop {
+            let cards: Vec<Room> = remaining.iter().map(|i| shop[*i].clone()).collect();
+            let placed = castle
+                .best_treasure_placement(&cards)
+                .and_then(|(i, pos, rot)| {
+                    let placed = castle.apply(Action::Place(cards[i].clone(), pos, rot));
+                    placed.ok().map(|placed| (i, placed))
+                });
+            match placed {
+                Some((i, placed)) => {
+                    castle = placed;
+                    used.push(remaining.remove(i));
+                }
+                None => return (castle, used),
+            }
+        }
+    }
+    /*
+     * (from, to, shop index, position) for every move after which the shop
+     * card fits at an empty position where it fits in no rotation right now.
+     * The position a move leaves behind is never reported. Results follow
+     * move order, then shop index and position.
+     */
+    pub fn placements_unlocked_by_move(&self, shop: &[Room]) -> Vec<(Pos, Pos, usize, Pos)> {
+        let placeable = |castle: &Castle| -> BTreeSet<(usize, Pos)> {
+            castle
+                .all_possible_placements(shop)
+                .into_iter()
+                .map(|(i, pos, _)| (i, pos))
+                .collect()
+        };
+        let before = placeable(self);
+        let mut unlocked = Vec::new();
+        for (from, to, rot) in self.all_possible_moves() {
+            let castle = match self.apply(Action::Move(from, to, rot)) {
+                Ok(castle) => castle,
+                Err(_) => continue,
+            };
+            for (i, pos) in placeable(&castle).difference(&before) {
+                if !self.rooms.contains_key(pos) && !unlocked.contains(&(from, to, *i, *pos)) {
+                    unlocked.push((from, to, *i, *pos));
+                }
+            }
+        }
+        unlocked
+    }
+    /*
+     * Number of castles, up to rotation, reflection and translation, that placing between
+     * one and depth cards of the shop can produce, each card used at most once.
+     * Every placement order is tried, so the cost grows quickly with the shop
+     * size and depth is capped at MAX_DISTINCT_DEPTH.
+     */
+    pub fn distinct_reachable(&self, shop: &[Room], depth: u8) -> usize {
+        let mut seen = BTreeSet::new();
+        let mut used = vec![false; shop.len()];
+        self.collect_distinct(shop, depth.min(MAX_DISTINCT_DEPTH), &mut used, &mut seen);
+        seen.len()
+    }
+    /*
+     * The first action of the line of up to depth actions reaching the best
+     * score_fn, with that score. A line scores the best castle along it, and
+     * each shop card is used at most once per line. A damaged castle only
+     * gets discards, and a lost one ends the line. Ties go to the first action
+     * in possible_actions order; depth is capped at MAX_BEST_ACTION_DEPTH.
+     */
+    pub fn best_action(
+        &self,
+        shop: &[Room],
+        depth: u8,
+        score_fn: impl Fn(&Castle) -> i32,
+    ) -> Option<(Action, i32)> {
+        let depth = depth.min(MAX_BEST_ACTION_DEPTH);
+        if depth == 0 || self.is_lost() {
+            return None;
+        }
+        let mut best: Option<(Action, i32)> = None;
+        for action in self.actions_iter(shop) {
+            let castle = match self.apply(action.clone()) {
+                Ok(castle) => castle,
+                Err(_) => continue,
+            };
+            let score = castle.line_score(&shop_after(shop, &action), depth - 1, &score_fn);
+            if best
+                .as_ref()
+                .is_none_or(|(_, best_score)| score > *best_score)
+            {
+                best = Some((action, score));
+            }
+        }
+        best
+    }
+    fn line_score(&self, shop: &[Room], depth: u8, score_fn: &impl Fn(&Castle) -> i32) -> i32 {
+        let mut best = score_fn(self);
+        if depth == 0 || self.is_lost() {
+            return best;
+        }
+        for action in self.actions_iter(shop) {
+            if let Ok(castle) = self.apply(action.clone()) {
+                best = best.max(castle.line_score(&shop_after(shop, &action), depth - 1, score_fn));
+            }
+        }
+        best
+    }
+    fn collect_distinct(
+        &self,
+        shop: &[Room],
+        depth: u8,
+        used: &mut [bool],
+        seen: &mut BTreeSet<Castle>,
+    ) {
+        if depth == 0 {
+            return;
+        }
+        for (i, pos, rot) in self.all_possible_placements(shop) {
+            if used[i] {
+                continue;
+            }
+            let mut castle = self.clone();
+            castle
+                .rooms
+                .insert(pos, PlacedRoom::from(shop[i].clone(), rot));
+            // A castle too wide to have a key is counted as it is.
+            seen.insert(castle.canonical_key().unwrap_or_else(|_| castle.clone()));
+            used[i] = true;
+            castle.collect_distinct(shop, depth - 1, used, seen);
+            used[i] = false;
+        }
+    }
+    fn expand(&self, shop: &[Room], used: &mut [bool], visited: &mut usize) -> usize {
+        *visited += 1;
+        let remaining = used.iter().filter(|u| !**u).count();
+        let mut best = 0;
+        for i in 0..shop.len() {
+            if used[i] {
+                continue;
+            }
+            for rot in [0, 90, 180, 270] {
+                let room = PlacedRoom::from(shop[i].clone(), rot);
+                let mut placements = self.possible_placements(&room);
+                placements.sort();
+                for pos in placements {
+                    if best == remaining || *visited >= EXPANSION_BUDGET {
+                        return best;
+                    }
+                    let mut castle = self.clone();
+                    castle.rooms.insert(pos, room.clone());
+                    used[i] = true;
+                    best = best.max(1 + castle.expand(shop, used, visited));
+                    used[i] = false;
+                }
+            }
+        }
+        best
+    }
+}
+
+/*
+ * The shop left once action has been taken: a placed room's card is used up
+ */
+fn shop_after(shop: &[Room], action: &Action) -> Vec<Room> {
+    let mut shop = shop.to_vec();
+    if let Action::Place(room, _, _) = action {
+        if let Some(index) = shop.iter().position(|card| card == room) {
+            shop.remove(index);
+        }
+    }
+    shop
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{empty_link_table, hall, room, small_vault, throne};
+    use crate::{Action, Castle, CastleError, Connection, Room};
+
+    #[test]
+    fn test_max_expansion_single_order() {
+        let mut throne = room(
+            "Throne Room",
+            0,
+            [
+                Connection::None,
+                Connection::Cross(false),
+                Connection::None,
+                Connection::None,
+            ],
+        );
+        throne.throne = true;
+        let castle = Castle::new(throne);
+        // The dead end must come after the corridor, or it plugs the only opening.
+        let shop: Vec<Room> = vec![
+            small_vault(),
+            room(
+                "Corridor",
+                0,
+                [
+                    Connection::None,
+                    Connection::Cross(false),
+                    Connection::None,
+                    Connection::Cross(false),
+                ],
+            ),
+        ];
+        assert_eq!(castle.max_expansion(&shop), 2);
+        assert_eq!(castle.max_expansion(&shop[..1]), 1);
+        assert_eq!(castle.max_expansion(&[]), 0);
+    }
+
+    #[test]
+    fn test_reachable_by_moves() {
+        let vault = small_vault();
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault, (1, 0), 0))
+            .unwrap();
+        let one = castle.reachable_by_moves((1, 0), 1).unwrap();
+        assert_eq!(
+            one.into_iter().collect::<Vec<_>>(),
+            vec![(-1, 0), (0, -1), (0, 1)]
+        );
+        // Coming back to where it started takes a hop somewhere else first.
+        let two = castle.reachable_by_moves((1, 0), 2).unwrap();
+        assert!(two.contains(&(1, 0)));
+        assert_eq!(two.len(), 4);
+        assert!(castle.reachable_by_moves((5, 5), 1).is_err());
+    }
+
+    #[test]
+    fn test_best_swap() {
+        let moon_hall = room(
+            "Moon Hall",
+            0,
+            [
+                Connection::None,
+                Connection::Moon(false),
+                Connection::None,
+                Connection::Moon(false),
+            ],
+        );
+        let passage = room(
+            "Passage",
+            0,
+            [
+                Connection::None,
+                Connection::Moon(false),
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let diamond_hall = room(
+            "Diamond Hall",
+            0,
+            [
+                Connection::None,
+                Connection::Diamond(false),
+                Connection::None,
+                Connection::Diamond(false),
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(moon_hall, (-1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(passage, (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(diamond_hall, (2, 0), 0))
+            .unwrap();
+        // The diamond hall links to the throne instead, and the moon hall to the passage.
+        assert_eq!(castle.best_swap().unwrap(), Some(((-1, 0), (2, 0), 1)));
+        assert_eq!(Castle::new(throne()).best_swap().unwrap(), None);
+        assert!(matches!(
+            castle.with_link_table(empty_link_table()).best_swap(),
+            Err(CastleError::InvalidConnection)
+        ));
+    }
+
+    #[test]
+    fn test_distinct_reachable() {
+        let vault = small_vault();
+        let castle = Castle::new(throne());
+        let shop = [vault.clone(), vault];
+        // One vault anywhere, then a second one beside or opposite it.
+        assert_eq!(castle.distinct_reachable(&shop, 1), 1);
+        assert_eq!(castle.distinct_reachable(&shop, 2), 3);
+        assert_eq!(castle.distinct_reachable(&shop, 9), 3);
+    }
+
+    #[test]
+    fn test_cheapest_new_link() {
+        let corner = room(
+            "Corner",
+            0,
+            [
+                Connection::Cross(false),
+                Connection::None,
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(corner.clone(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(corner, (1, -1), 270))
+            .unwrap();
+        let vault = small_vault();
+        let bridge = room(
+            "Bridge",
+            0,
+            [
+                Connection::None,
+                Connection::Cross(false),
+                Connection::Cross(false),
+                Connection::None,
+            ],
+        );
+        // Only the bridge fits the gap between the throne and the far corner.
+        assert_eq!(
+            castle.cheapest_new_link(&[vault.clone(), bridge]).unwrap(),
+            Some((1, (0, -1), 0))
+        );
+        assert_eq!(castle.cheapest_new_link(&[]).unwrap(), None);
+        let shop = [vault];
+        assert_eq!(
+            castle.cheapest_new_link(&shop).unwrap().map(|(i, _, _)| i),
+            Some(0)
+        );
+        assert!(matches!(
+            castle
+                .with_link_table(empty_link_table())
+                .cheapest_new_link(&shop),
+            Err(CastleError::InvalidConnection)
+        ));
+    }
+
+    #[test]
+    fn test_placements_unlocked_by_move() {
+        let mut throne = room(
+            "Throne Room",
+            0,
+            [
+                Connection::Cross(false),
+                Connection::Cross(false),
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        throne.throne = true;
+        let shop = [small_vault()];
+        let castle = Castle::new(throne.clone())
+            .apply(Action::Place(hall(), (1, 0), 0))
+            .unwrap();
+        // Moving the hall opens a new cell past its far end. No move reports
+        // the cell it leaves behind.
+        let unlocked = castle.placements_unlocked_by_move(&shop);
+        assert!(unlocked.iter().all(|(from, _, _, pos)| pos != from));
+        let by_hall: Vec<_> = unlocked
+            .into_iter()
+            .filter(|(from, ..)| *from == (1, 0))
+            .collect();
+        assert_eq!(
+            by_hall,
+            vec![((1, 0), (-1, 0), 0, (-2, 0)), ((1, 0), (0, -1), 0, (0, -2))]
+        );
+        // A vault has no far end, so moving one unlocks nothing.
+        let castle = Castle::new(throne)
+            .apply(Action::Place(small_vault(), (0, -1), 270))
+            .unwrap();
+        assert!(castle.placements_unlocked_by_move(&shop).is_empty());
+    }
+
+    #[test]
+    fn test_best_action() {
+        let hall = hall();
+        let vault = room(
+            "Small Vault",
+            2,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(hall, (1, 0), 0))
+            .unwrap();
+        let shop = [vault.clone()];
+        let treasure = |castle: &Castle| castle.get_treasure().unwrap() as i32;
+        // Past the hall the vault would sit behind an unpowered link.
+        let behind = castle
+            .apply(Action::Place(vault.clone(), (2, 0), 0))
+            .unwrap();
+        assert_eq!(treasure(&behind), 0);
+        let (action, score) = castle.best_action(&shop, 1, treasure).unwrap();
+        assert_eq!(score, 2);
+        match action {
+            Action::Place(_, pos, _) => assert!([(0, -1), (0, 1), (-1, 0)].contains(&pos)),
+            _ => panic!("expected a placement, got {:?}", action),
+        }
+        assert_eq!(
+            castle
+                .best_action(&[vault.clone(), vault.clone()], 2, treasure)
+                .map(|(_, s)| s),
+            Some(4)
+        );
+        assert_eq!(castle.best_action(&shop, 0, treasure), None);
+
+        let mut damaged = castle.clone();
+        damaged.damage = 1;
+        assert!(matches!(
+            damaged.best_action(&shop, 2, treasure),
+            Some((Action::Discard(_), _))
+        ));
+        damaged.damage = 2;
+        assert!(damaged.is_lost());
+        assert_eq!(damaged.best_action(&shop, 2, treasure), None);
+    }
+
+    #[test]
+    fn test_greedy_fill() {
+        let small_vault = small_vault();
+        let wall = room("Wall", 0, [Connection::None; 4]);
+        let vault = room(
+            "Vault",
+            3,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Cross(true),
+            ],
+        );
+        let castle = Castle::new(throne());
+        let shop = [small_vault, wall, vault];
+        let (filled, used) = castle.greedy_fill(&shop);
+        // The wall connects to nothing, so only the two vaults go in, biggest first.
+        assert_eq!(used, vec![2, 0]);
+        assert_eq!(filled.rooms.len(), castle.rooms.len() + 2);
+        assert_eq!(filled.get_treasure().unwrap(), 4);
+        assert_eq!(
+            castle.best_treasure_placement(&shop).map(|(i, _, _)| i),
+            Some(2)
+        );
+
+        let mut damaged = castle;
+        damaged.damage = 1;
+        assert_eq!(damaged.best_treasure_placement(&shop), None);
+        assert_eq!(damaged.greedy_fill(&shop), (damaged.clone(), vec![]));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{Action, Castle, PlacedRoom, PlacementRule, Pos, Result};
+
+/*
+ * What a single action changed: the previous content of every position it
+ * touched, and the previous damage.
+ */
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct CastleSnapshot {
+    rooms: Vec<(Pos, Option<PlacedRoom>)>,
+    damage: u8,
+}
+
+impl Castle {
+    /*
+     * Same as apply, but changes the castle in place and returns what is needed
+     * to undo it. Place, Move and Swap only touch the rooms involved; Discard
+     * and Damage are rare enough that they go through apply.
+     */
+    pub fn apply_snapshotting(&mut self, action: Action) -> Result<CastleSnapshot> {
+        self.apply_snapshotting_with(action, PlacementRule::Strict)
+    }
+    pub fn apply_snapshotting_with(
+        &mut self,
+        action: Action,
+        rule: PlacementRule,
+    ) -> Result<CastleSnapshot> {
+        self.check_phase(&action)?;
+        let damage = self.damage;
+        let rooms = match action {
+            Action::Place(room, pos, rot) => self.place_room(room, pos, rot, rule)?,
+            Action::Move(from, to, rot) => self.move_room(from, to, rot, rule)?,
+            Action::Swap(pos_1, pos_2) => self.swap_rooms(pos_1, pos_2, rule)?,
+            action => {
+                let castle = self.apply_with(action, rule)?;
+                let removed = self
+                    .rooms
+                    .iter()
+                    .filter(|(pos, _)| !castle.rooms.contains_key(pos))
+                    .map(|(pos, room)| (*pos, Some(room.clone())))
+                    .collect();
+                *self = castle;
+                removed
+            }
+        };
+        Ok(CastleSnapshot { rooms, damage })
+    }
+    pub fn restore(&mut self, snapshot: CastleSnapshot) {
+        for (pos, room) in snapshot.rooms.into_iter().rev() {
+            match room {
+                Some(room) => self.rooms.insert(pos, room),
+                None => self.rooms.remove(&pos),
+            };
+        }
+        self.damage = snapshot.damage;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{hall, room, small_vault, throne};
+    use crate::{Action, Castle, CastleError, Connection, PlacementRule};
+
+    #[test]
+    fn test_apply_snapshotting_restore() {
+        let hall = hall();
+        let vault = small_vault();
+        let original = Castle::new(throne())
+            .apply(Action::Place(hall.clone(), (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(vault.clone(), (2, 0), 0))
+            .unwrap();
+        let actions = vec![
+            Action::Place(vault, (-1, 0), 180),
+            Action::Move((2, 0), (0, 1), 90),
+            Action::Swap((1, 0), (0, 0)),
+            Action::Swap((2, 0), (0, 0)),
+            Action::Damage(2, 0, 0),
+        ];
+        for action in actions {
+            let mut castle = original.clone();
+            let applied = original.apply(action.clone());
+            match castle.apply_snapshotting(action) {
+                Ok(snapshot) => {
+                    assert_eq!(castle, applied.unwrap());
+                    castle.restore(snapshot);
+                }
+                Err(_) => assert!(applied.is_err()),
+            }
+            assert_eq!(castle, original);
+        }
+        let mut castle = original.clone();
+        assert!(castle
+            .apply_snapshotting(Action::Place(hall, (5, 5), 0))
+            .is_err());
+        assert_eq!(castle, original);
+    }
+
+    #[test]
+    fn test_apply_snapshotting_lenient() {
+        let corridor = room(
+            "Corridor",
+            0,
+            [
+                Connection::None,
+                Connection::Cross(false),
+                Connection::Cross(false),
+                Connection::None,
+            ],
+        );
+        let original = Castle::new(throne())
+            .apply(Action::Place(corridor, (0, -1), 0))
+            .unwrap()
+            .apply(Action::Place(small_vault(), (1, -1), 0))
+            .unwrap();
+        // Connects to the throne on the west, but faces the vault's wall on the north.
+        let hall = room(
+            "Hall",
+            0,
+            [
+                Connection::Cross(false),
+                Connection::None,
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let action = Action::Place(hall, (1, 0), 0);
+        let mut castle = original.clone();
+        assert!(matches!(
+            castle.apply_snapshotting(action.clone()),
+            Err(CastleError::InvalidConnection)
+        ));
+        assert_eq!(castle, original);
+
+        let applied = original
+            .apply_with(action.clone(), PlacementRule::Lenient)
+            .unwrap();
+        let snapshot = castle
+            .apply_snapshotting_with(action, PlacementRule::Lenient)
+            .unwrap();
+        assert_eq!(castle, applied);
+        castle.restore(snapshot);
+        assert_eq!(castle, original);
+    }
+}
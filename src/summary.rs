@@ -0,0 +1,182 @@
+use crate::{Castle, Pos, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct CastleSummary {
+    pub rooms: usize,
+    pub damage: u8,
+    pub links: (u8, u8, u8, u8),
+    pub treasure: u32,
+    pub powered_rooms: usize,
+    pub is_lost: bool,
+    pub bounding_box: Option<(Pos, Pos)>,
+}
+
+/*
+ * Win conditions for objective modes. Only the fields that are set are
+ * checked, links per kind as (diamond, cross, moon, wild).
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+pub struct Objective {
+    pub min_treasure: Option<u32>,
+    pub min_links: Option<(u8, u8, u8, u8)>,
+    pub min_rooms: Option<usize>,
+}
+
+impl Castle {
+    pub fn summary(&self) -> Result<CastleSummary> {
+        Ok(CastleSummary {
+            rooms: self.rooms.len(),
+            damage: self.damage,
+            links: self.get_links()?,
+            treasure: self.get_treasure()?,
+            powered_rooms: self.powered_rooms().len(),
+            is_lost: self.is_lost(),
+            bounding_box: self.bounding_box(),
+        })
+    }
+    /*
+     * Checks a treasure total reported by a client against the castle
+     */
+    pub fn verify_treasure(&self, claimed: u32) -> Result<bool> {
+        Ok(self.get_treasure()? == claimed)
+    }
+    pub fn verify_summary(&self, claimed: &CastleSummary) -> Result<bool> {
+        Ok(self.summary()? == *claimed)
+    }
+    pub fn meets_objective(&self, objective: &Objective) -> Result<bool> {
+        if let Some(min_treasure) = objective.min_treasure {
+            if self.get_treasure()? < min_treasure {
+                return Ok(false);
+            }
+        }
+        if let Some((diamond, cross, moon, wild)) = objective.min_links {
+            let links = self.get_links()?;
+            if links.0 < diamond || links.1 < cross || links.2 < moon || links.3 < wild {
+                return Ok(false);
+            }
+        }
+        if let Some(min_rooms) = objective.min_rooms {
+            if self.rooms.len() < min_rooms {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{room, throne};
+    use crate::{Action, Castle, Connection, Objective};
+
+    #[test]
+    fn test_summary() {
+        let east = room(
+            "Vault",
+            3,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Cross(true),
+            ],
+        );
+        let south = room(
+            "Vault",
+            2,
+            [
+                Connection::None,
+                Connection::Moon(true),
+                Connection::None,
+                Connection::Cross(false),
+            ],
+        );
+        let mut castle = Castle::new(throne())
+            .apply(Action::Place(east, (1, 0), 0))
+            .unwrap()
+            .apply(Action::Place(south, (0, 1), 90))
+            .unwrap();
+        castle.damage = 1;
+        let summary = castle.summary().unwrap();
+        assert_eq!(summary.rooms, castle.rooms.len());
+        assert_eq!(summary.damage, 1);
+        assert_eq!(summary.links, castle.get_links().unwrap());
+        assert_eq!(summary.treasure, castle.get_treasure().unwrap());
+        assert_eq!(summary.treasure, 5);
+        assert_eq!(summary.powered_rooms, 3);
+        assert_eq!(summary.is_lost, castle.is_lost());
+        assert_eq!(summary.bounding_box, Some(((0, 0), (1, 1))));
+    }
+
+    #[test]
+    fn test_verify() {
+        let vault = room(
+            "Vault",
+            3,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Cross(true),
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault, (1, 0), 0))
+            .unwrap();
+        assert!(castle.verify_treasure(3).unwrap());
+        assert!(!castle.verify_treasure(4).unwrap());
+
+        let mut summary = castle.summary().unwrap();
+        assert!(castle.verify_summary(&summary).unwrap());
+        summary.links = (1, 1, 0, 0);
+        assert!(!castle.verify_summary(&summary).unwrap());
+    }
+
+    #[test]
+    fn test_meets_objective() {
+        let vault = room(
+            "Vault",
+            2,
+            [
+                Connection::None,
+                Connection::None,
+                Connection::None,
+                Connection::Cross(true),
+            ],
+        );
+        let castle = Castle::new(throne())
+            .apply(Action::Place(vault, (1, 0), 0))
+            .unwrap();
+        assert!(castle.meets_objective(&Objective::default()).unwrap());
+        let treasure = Objective {
+            min_treasure: Some(2),
+            ..Objective::default()
+        };
+        assert!(castle.meets_objective(&treasure).unwrap());
+        let links = Objective {
+            min_links: Some((0, 1, 0, 0)),
+            min_rooms: Some(2),
+            ..treasure
+        };
+        assert!(castle.meets_objective(&links).unwrap());
+        assert!(!castle
+            .meets_objective(&Objective {
+                min_treasure: Some(3),
+                ..links
+            })
+            .unwrap());
+        assert!(!castle
+            .meets_objective(&Objective {
+                min_links: Some((0, 1, 1, 0)),
+                ..links
+            })
+            .unwrap());
+        assert!(!castle
+            .meets_objective(&Objective {
+                min_rooms: Some(3),
+                ..links
+            })
+            .unwrap());
+    }
+}